@@ -0,0 +1,261 @@
+use crate::bars::BarTrigger;
+use crate::models::general::{BaseVolumeBar, DollarBar, PnlBar, Side, TradeTx, VolumeBar};
+use crate::models::traits::BarBuilder;
+
+/// Dollar Bar Builder
+/// Closes an OHLC bar once traded notional reaches the limit
+#[derive(Debug, Clone)]
+pub struct DollarBarBuilder {
+  pub notional_limit: f64,
+  bar: DollarBar,
+}
+
+impl DollarBarBuilder {
+  pub fn new(notional_limit: f64) -> Self {
+    Self { notional_limit, bar: DollarBar::new() }
+  }
+}
+
+impl BarBuilder for DollarBarBuilder {
+  type Bar = DollarBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    let price_quote: f64 = trade_tx.price_quote;
+    if self.bar.datetime.is_empty() {
+      self.bar.datetime = trade_tx.block_time.clone();
+      self.bar.open = price_quote;
+      self.bar.high = price_quote;
+      self.bar.low = price_quote;
+    }
+    if price_quote > self.bar.high { self.bar.high = price_quote; }
+    if price_quote < self.bar.low { self.bar.low = price_quote; }
+    self.bar.close = price_quote;
+    self.bar.notional += trade_tx.notional;
+  }
+
+  fn should_close(&self) -> bool {
+    self.bar.notional >= self.notional_limit
+  }
+
+  fn finish(&self) -> DollarBar {
+    self.bar.clone()
+  }
+
+  fn reset(&mut self) {
+    self.bar = DollarBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.datetime.is_empty()
+  }
+}
+
+/// Base Volume Bar Builder
+/// Closes an OHLC bar once traded base quantity reaches the limit
+#[derive(Debug, Clone)]
+pub struct BaseVolumeBarBuilder {
+  pub base_limit: f64,
+  bar: BaseVolumeBar,
+}
+
+impl BaseVolumeBarBuilder {
+  pub fn new(base_limit: f64) -> Self {
+    Self { base_limit, bar: BaseVolumeBar::new() }
+  }
+}
+
+impl BarBuilder for BaseVolumeBarBuilder {
+  type Bar = BaseVolumeBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    let price_quote: f64 = trade_tx.price_quote;
+    if self.bar.datetime.is_empty() {
+      self.bar.datetime = trade_tx.block_time.clone();
+      self.bar.open = price_quote;
+      self.bar.high = price_quote;
+      self.bar.low = price_quote;
+    }
+    if price_quote > self.bar.high { self.bar.high = price_quote; }
+    if price_quote < self.bar.low { self.bar.low = price_quote; }
+    self.bar.close = price_quote;
+    self.bar.volume_base += trade_tx.volume_base;
+  }
+
+  fn should_close(&self) -> bool {
+    self.bar.volume_base >= self.base_limit
+  }
+
+  fn finish(&self) -> BaseVolumeBar {
+    self.bar.clone()
+  }
+
+  fn reset(&mut self) {
+    self.bar = BaseVolumeBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.datetime.is_empty()
+  }
+}
+
+/// Volume Bar Builder
+/// Accumulates buy and sell quote volume until its trigger is reached,
+/// splitting trades flagged as wash from organic volume
+#[derive(Debug, Clone)]
+pub struct VolumeBarBuilder {
+  pub trigger: BarTrigger,
+  progress: f64,
+  bar: VolumeBar,
+}
+
+impl VolumeBarBuilder {
+  pub fn new(trigger: BarTrigger) -> Self {
+    Self { trigger, progress: 0.0, bar: VolumeBar::new() }
+  }
+}
+
+impl BarBuilder for VolumeBarBuilder {
+  type Bar = VolumeBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    if self.bar.datetime.is_empty() { self.bar.datetime = trade_tx.block_time.clone(); }
+    let volume_quote: f64 = trade_tx.volume_quote;
+    match (&trade_tx.side, trade_tx.wash_reason.is_some()) {
+      (Side::Buy, false) => self.bar.organic_volume_buys += volume_quote,
+      (Side::Buy, true) => self.bar.wash_volume_buys += volume_quote,
+      (Side::Sell, false) => self.bar.organic_volume_sells += volume_quote,
+      (Side::Sell, true) => self.bar.wash_volume_sells += volume_quote
+    }
+    match trade_tx.side {
+      Side::Buy => self.bar.volume_buys += volume_quote,
+      Side::Sell => self.bar.volume_sells += volume_quote
+    }
+    self.progress += self.trigger.measure(trade_tx);
+  }
+
+  fn should_close(&self) -> bool {
+    self.progress >= self.trigger.threshold()
+  }
+
+  fn finish(&self) -> VolumeBar {
+    let mut bar: VolumeBar = self.bar.clone();
+    let volume_quote: f64 = bar.volume_buys + bar.volume_sells;
+    if volume_quote > 0.0 { bar.wash_score = (bar.wash_volume_buys + bar.wash_volume_sells) / volume_quote; }
+    bar
+  }
+
+  fn reset(&mut self) {
+    self.progress = 0.0;
+    self.bar = VolumeBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.datetime.is_empty()
+  }
+}
+
+/// Pnl Bar Builder
+/// Sums realized pnl over trades until its trigger is reached
+/// A sell with positive internal realized pnl counts as a winning close and negative as a losing close
+#[derive(Debug, Clone)]
+pub struct PnlBarBuilder {
+  pub trigger: BarTrigger,
+  progress: f64,
+  bar: PnlBar,
+}
+
+impl PnlBarBuilder {
+  pub fn new(trigger: BarTrigger) -> Self {
+    Self { trigger, progress: 0.0, bar: PnlBar::new() }
+  }
+}
+
+impl BarBuilder for PnlBarBuilder {
+  type Bar = PnlBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    if self.bar.datetime.is_empty() { self.bar.datetime = trade_tx.block_time.clone(); }
+    self.bar.internal_realized_pnl += trade_tx.account_realized_pnl;
+    self.bar.external_realized_pnl += trade_tx.account_external_pnl;
+    self.bar.realized_pnl_gross += trade_tx.account_realized_pnl_gross;
+    self.bar.realized_pnl_net += trade_tx.account_realized_pnl_net;
+    self.bar.swap_fees_quote += trade_tx.swap_fee_quote;
+    self.bar.gas_fees_quote += trade_tx.gas_fee_quote;
+    self.bar.unrealized_pnl = trade_tx.market_unrealized_pnl;
+    self.bar.unrealized_pnl_gross = trade_tx.market_unrealized_pnl_gross;
+    self.bar.unrealized_pnl_net = trade_tx.market_unrealized_pnl_net;
+    self.bar.open_interest_base = trade_tx.market_open_interest_base;
+    self.bar.trade_count += 1;
+    if trade_tx.side == Side::Sell {
+      if trade_tx.account_realized_pnl > 0.0 { self.bar.count_won += 1; }
+      if trade_tx.account_realized_pnl < 0.0 { self.bar.count_lost += 1; }
+    }
+    self.progress += self.trigger.measure(trade_tx);
+  }
+
+  fn should_close(&self) -> bool {
+    self.progress >= self.trigger.threshold()
+  }
+
+  fn finish(&self) -> PnlBar {
+    self.bar.clone()
+  }
+
+  fn reset(&mut self) {
+    self.progress = 0.0;
+    self.bar = PnlBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.datetime.is_empty()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::models::general::WashReason;
+
+  fn trade_tx(side: Side, realized_pnl: f64, unrealized_pnl: f64) -> TradeTx {
+    let mut trade_tx: TradeTx = TradeTx::test_trade(1, side, "0xa", 0.0, 0.0);
+    trade_tx.account_realized_pnl = realized_pnl;
+    trade_tx.account_external_pnl = if realized_pnl == 0.0 { 0.0 } else { 0.5 };
+    trade_tx.market_unrealized_pnl = unrealized_pnl;
+    trade_tx.market_open_interest_base = unrealized_pnl * 10.0;
+    trade_tx
+  }
+
+  #[test]
+  fn it_sums_pnl_over_the_bar() {
+    let mut builder: PnlBarBuilder = PnlBarBuilder::new(BarTrigger::TradeCount(3));
+    assert!(builder.sample(&trade_tx(Side::Sell, 2.0, 1.0)).is_none());
+    assert!(builder.sample(&trade_tx(Side::Buy, 0.0, 3.0)).is_none());
+    let bar: PnlBar = builder.sample(&trade_tx(Side::Sell, -0.5, 2.0)).expect("Expected pnl bar to close");
+    assert_eq!(bar.internal_realized_pnl, 1.5);
+    assert_eq!(bar.external_realized_pnl, 1.0);
+    assert_eq!(bar.unrealized_pnl, 2.0);
+    assert_eq!(bar.open_interest_base, 20.0);
+    assert_eq!(bar.trade_count, 3);
+    assert_eq!((bar.count_won, bar.count_lost), (1, 1));
+  }
+
+  #[test]
+  fn it_splits_organic_and_wash_volume() {
+    let mut builder: VolumeBarBuilder = VolumeBarBuilder::new(BarTrigger::TradeCount(3));
+    let mut organic_buy: TradeTx = trade_tx(Side::Buy, 0.0, 0.0);
+    organic_buy.volume_quote = 3.0;
+    let mut wash_buy: TradeTx = organic_buy.clone();
+    wash_buy.wash_reason = Some(WashReason::FreshWalletPingPong);
+    let mut wash_sell: TradeTx = trade_tx(Side::Sell, 0.0, 0.0);
+    wash_sell.volume_quote = 2.0;
+    wash_sell.wash_reason = Some(WashReason::SelfTrade);
+
+    builder.sample(&organic_buy);
+    builder.sample(&wash_buy);
+    let bar: VolumeBar = builder.sample(&wash_sell).expect("Expected volume bar to close");
+    assert_eq!((bar.volume_buys, bar.volume_sells), (6.0, 2.0));
+    assert_eq!((bar.organic_volume_buys, bar.wash_volume_buys), (3.0, 3.0));
+    assert_eq!((bar.organic_volume_sells, bar.wash_volume_sells), (0.0, 2.0));
+    assert_eq!(bar.wash_score, 0.625);
+  }
+}
@@ -0,0 +1,302 @@
+use crate::models::general::{InformationBar, Side, TradeTx};
+use crate::models::traits::BarBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Information Measure
+/// What each trade contributes to an imbalance or run, signed by its side
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum InformationMeasure {
+  Tick,
+  Volume,
+  Dollar
+}
+
+impl InformationMeasure {
+  pub fn measure(&self, trade_tx: &TradeTx) -> f64 {
+    match self {
+      InformationMeasure::Tick => 1.0,
+      InformationMeasure::Volume => trade_tx.volume_base,
+      InformationMeasure::Dollar => trade_tx.notional
+    }
+  }
+
+  /// Signed Measure
+  /// Positive for buys and negative for sells
+  pub fn signed_measure(&self, trade_tx: &TradeTx) -> f64 {
+    match trade_tx.side {
+      Side::Buy => self.measure(trade_tx),
+      Side::Sell => -self.measure(trade_tx)
+    }
+  }
+}
+
+/// Exponentially weighted moving average step
+fn ewma(previous: f64, value: f64, alpha: f64) -> f64 {
+  previous + alpha * (value - previous)
+}
+
+/// Tick Bar Builder
+/// Closes an OHLCV bar every tick_limit trades
+#[derive(Debug, Clone)]
+pub struct TickBarBuilder {
+  pub tick_limit: u64,
+  bar: InformationBar,
+}
+
+impl TickBarBuilder {
+  pub fn new(tick_limit: u64) -> Self {
+    Self { tick_limit, bar: InformationBar::new() }
+  }
+}
+
+impl BarBuilder for TickBarBuilder {
+  type Bar = InformationBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    self.bar.update(trade_tx);
+  }
+
+  fn should_close(&self) -> bool {
+    self.bar.trade_count >= self.tick_limit
+  }
+
+  fn finish(&self) -> InformationBar {
+    let mut bar: InformationBar = self.bar.clone();
+    bar.threshold = self.tick_limit as f64;
+    bar
+  }
+
+  fn reset(&mut self) {
+    self.bar = InformationBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.trade_count == 0
+  }
+}
+
+/// Imbalance Bar Builder
+/// Closes an OHLCV bar once the signed order flow imbalance exceeds its expected size,
+/// E[T] * |E[b * v]|, with both expectations tracked as EWMAs over closed bars
+/// The threshold is floored at sqrt(E[T]) * E[v], the typical imbalance of E[T] trades of balanced flow,
+/// so bars do not close on every trade while E[b * v] is near zero
+/// The first bar closes after expected_ticks trades to seed the expected imbalance
+#[derive(Debug, Clone)]
+pub struct ImbalanceBarBuilder {
+  pub measure: InformationMeasure,
+  pub ewma_alpha: f64,
+  pub expected_ticks: f64,
+  pub expected_imbalance: Option<f64>,
+  pub expected_measure: Option<f64>,
+  imbalance: f64,
+  total_measure: f64,
+  bar: InformationBar,
+}
+
+impl ImbalanceBarBuilder {
+  pub fn new(measure: InformationMeasure, expected_ticks: f64, ewma_alpha: f64) -> Self {
+    Self {
+      measure, ewma_alpha, expected_ticks, expected_imbalance: None, expected_measure: None,
+      imbalance: 0.0, total_measure: 0.0, bar: InformationBar::new()
+    }
+  }
+
+  /// Threshold
+  /// Expected absolute imbalance of a bar, None until the first bar has closed
+  pub fn threshold(&self) -> Option<f64> {
+    let expected_imbalance: f64 = self.expected_imbalance?;
+    let floor: f64 = self.expected_ticks.sqrt() * self.expected_measure.unwrap_or(0.0);
+    Some((self.expected_ticks * expected_imbalance.abs()).max(floor))
+  }
+}
+
+impl BarBuilder for ImbalanceBarBuilder {
+  type Bar = InformationBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    self.bar.update(trade_tx);
+    self.imbalance += self.measure.signed_measure(trade_tx);
+    self.total_measure += self.measure.measure(trade_tx);
+  }
+
+  fn should_close(&self) -> bool {
+    match self.threshold() {
+      Some(threshold) => self.imbalance.abs() >= threshold,
+      None => self.bar.trade_count as f64 >= self.expected_ticks
+    }
+  }
+
+  fn finish(&self) -> InformationBar {
+    let mut bar: InformationBar = self.bar.clone();
+    bar.threshold = self.threshold().unwrap_or(self.imbalance.abs());
+    bar
+  }
+
+  fn reset(&mut self) {
+    let trade_count: f64 = self.bar.trade_count as f64;
+    if trade_count > 0.0 {
+      let bar_imbalance: f64 = self.imbalance / trade_count;
+      let bar_measure: f64 = self.total_measure / trade_count;
+      self.expected_imbalance = Some(match self.expected_imbalance {
+        Some(expected_imbalance) => ewma(expected_imbalance, bar_imbalance, self.ewma_alpha),
+        None => bar_imbalance
+      });
+      self.expected_measure = Some(match self.expected_measure {
+        Some(expected_measure) => ewma(expected_measure, bar_measure, self.ewma_alpha),
+        None => bar_measure
+      });
+      self.expected_ticks = ewma(self.expected_ticks, trade_count, self.ewma_alpha).max(1.0);
+    }
+    self.imbalance = 0.0;
+    self.total_measure = 0.0;
+    self.bar = InformationBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.trade_count == 0
+  }
+}
+
+/// Run Bar Builder
+/// Closes an OHLCV bar once the larger of the buy and sell runs exceeds its expected size,
+/// E[T] * max(E[v * buy], E[v * sell]), with the expectations tracked as EWMAs over closed bars
+/// The first bar closes after expected_ticks trades to seed the expected runs
+#[derive(Debug, Clone)]
+pub struct RunBarBuilder {
+  pub measure: InformationMeasure,
+  pub ewma_alpha: f64,
+  pub expected_ticks: f64,
+  pub expected_runs: Option<(f64, f64)>,
+  buy_run: f64,
+  sell_run: f64,
+  bar: InformationBar,
+}
+
+impl RunBarBuilder {
+  pub fn new(measure: InformationMeasure, expected_ticks: f64, ewma_alpha: f64) -> Self {
+    Self { measure, ewma_alpha, expected_ticks, expected_runs: None, buy_run: 0.0, sell_run: 0.0, bar: InformationBar::new() }
+  }
+
+  /// Threshold
+  /// Expected size of the dominant run, None until the first bar has closed
+  pub fn threshold(&self) -> Option<f64> {
+    self.expected_runs.map(|(expected_buys, expected_sells)| self.expected_ticks * expected_buys.max(expected_sells))
+  }
+}
+
+impl BarBuilder for RunBarBuilder {
+  type Bar = InformationBar;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    self.bar.update(trade_tx);
+    match trade_tx.side {
+      Side::Buy => self.buy_run += self.measure.measure(trade_tx),
+      Side::Sell => self.sell_run += self.measure.measure(trade_tx)
+    }
+  }
+
+  fn should_close(&self) -> bool {
+    match self.threshold() {
+      Some(threshold) => self.buy_run.max(self.sell_run) >= threshold,
+      None => self.bar.trade_count as f64 >= self.expected_ticks
+    }
+  }
+
+  fn finish(&self) -> InformationBar {
+    let mut bar: InformationBar = self.bar.clone();
+    bar.threshold = self.threshold().unwrap_or(self.buy_run.max(self.sell_run));
+    bar
+  }
+
+  fn reset(&mut self) {
+    let trade_count: f64 = self.bar.trade_count as f64;
+    if trade_count > 0.0 {
+      let bar_buys: f64 = self.buy_run / trade_count;
+      let bar_sells: f64 = self.sell_run / trade_count;
+      self.expected_runs = Some(match self.expected_runs {
+        Some((expected_buys, expected_sells)) => (
+          ewma(expected_buys, bar_buys, self.ewma_alpha),
+          ewma(expected_sells, bar_sells, self.ewma_alpha)
+        ),
+        None => (bar_buys, bar_sells)
+      });
+      self.expected_ticks = ewma(self.expected_ticks, trade_count, self.ewma_alpha).max(1.0);
+    }
+    self.buy_run = 0.0;
+    self.sell_run = 0.0;
+    self.bar = InformationBar::new();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.trade_count == 0
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_closes_tick_bars_every_n_trades() {
+    let mut builder: TickBarBuilder = TickBarBuilder::new(2);
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 2.0, 1.0)).is_none());
+    let bar: InformationBar = builder.sample(&TradeTx::test_trade(1, Side::Sell, "0xa", 1.0, 3.0)).expect("Expected tick bar to close");
+    assert_eq!((bar.open, bar.high, bar.low, bar.close), (1.0, 3.0, 1.0, 3.0));
+    assert_eq!((bar.volume_buys, bar.volume_sells), (2.0, 3.0));
+    assert_eq!(bar.trade_count, 2);
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).is_none());
+  }
+
+  #[test]
+  fn it_adapts_imbalance_threshold() {
+    let mut builder: ImbalanceBarBuilder = ImbalanceBarBuilder::new(InformationMeasure::Tick, 4.0, 0.5);
+    // Warm-up bar of 3 buys and 1 sell seeds E[b] = 0.5
+    for side in [Side::Buy, Side::Buy, Side::Sell] {
+      assert!(builder.sample(&TradeTx::test_trade(1, side, "0xa", 1.0, 1.0)).is_none());
+    }
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).is_some());
+    assert_eq!(builder.threshold(), Some(2.0));
+
+    // Two straight buys reach the expected imbalance of 4 * 0.5
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).is_none());
+    let bar: InformationBar = builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).expect("Expected imbalance bar to close");
+    assert_eq!(bar.trade_count, 2);
+    assert_eq!(bar.threshold, 2.0);
+    // E[T] = 4 + 0.5 * (2 - 4), E[b] = 0.5 + 0.5 * (1 - 0.5)
+    assert_eq!(builder.expected_ticks, 3.0);
+    assert_eq!(builder.expected_imbalance, Some(0.75));
+  }
+
+  #[test]
+  fn it_floors_imbalance_threshold_under_balanced_flow() {
+    let mut builder: ImbalanceBarBuilder = ImbalanceBarBuilder::new(InformationMeasure::Tick, 4.0, 0.5);
+    // Balanced warm-up bar seeds E[b] = 0, leaving the sqrt(4) * 1 floor
+    for side in [Side::Buy, Side::Sell, Side::Buy] {
+      assert!(builder.sample(&TradeTx::test_trade(1, side, "0xa", 1.0, 1.0)).is_none());
+    }
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Sell, "0xa", 1.0, 1.0)).is_some());
+    assert_eq!(builder.threshold(), Some(2.0));
+
+    for side in [Side::Buy, Side::Sell, Side::Buy, Side::Sell] {
+      assert!(builder.sample(&TradeTx::test_trade(1, side, "0xa", 1.0, 1.0)).is_none());
+    }
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).is_none());
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).is_some());
+  }
+
+  #[test]
+  fn it_closes_run_bars_on_dominant_side() {
+    let mut builder: RunBarBuilder = RunBarBuilder::new(InformationMeasure::Volume, 2.0, 0.5);
+    builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 4.0, 1.0));
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Sell, "0xa", 2.0, 1.0)).is_some());
+    // E[T] * max(E[v buy], E[v sell]) = 2 * max(2, 1)
+    assert_eq!(builder.threshold(), Some(4.0));
+
+    // Interleaved sells do not break the buy run
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 3.0, 1.0)).is_none());
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Sell, "0xa", 3.0, 1.0)).is_none());
+    let bar: InformationBar = builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 1.0)).expect("Expected run bar to close");
+    assert_eq!(bar.trade_count, 3);
+    assert_eq!(bar.volume_base, 7.0);
+  }
+}
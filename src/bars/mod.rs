@@ -0,0 +1,147 @@
+pub mod activity;
+pub mod information;
+pub mod time;
+
+use crate::bars::activity::{BaseVolumeBarBuilder, DollarBarBuilder, PnlBarBuilder, VolumeBarBuilder};
+use crate::bars::information::{ImbalanceBarBuilder, RunBarBuilder, TickBarBuilder};
+use crate::bars::time::TimeBarBuilder;
+use crate::models::general::{BaseVolumeBar, DollarBar, InformationBar, PnlBar, ReservePoint, TimeBar, TradeTx, VolumeBar};
+use crate::models::traits::BarBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Bar Trigger
+/// The activity a bar accumulates and the threshold at which it closes
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub enum BarTrigger {
+  Notional(f64),
+  BaseVolume(f64),
+  TradeCount(u64)
+}
+
+impl BarTrigger {
+  /// Measure
+  /// Returns how far a trade moves the bar towards its threshold
+  pub fn measure(&self, trade_tx: &TradeTx) -> f64 {
+    match self {
+      BarTrigger::Notional(_) => trade_tx.notional,
+      BarTrigger::BaseVolume(_) => trade_tx.volume_base,
+      BarTrigger::TradeCount(_) => 1.0
+    }
+  }
+
+  pub fn threshold(&self) -> f64 {
+    match self {
+      BarTrigger::Notional(limit) => *limit,
+      BarTrigger::BaseVolume(limit) => *limit,
+      BarTrigger::TradeCount(count) => *count as f64
+    }
+  }
+}
+
+/// Bar Kind
+/// Names one of the sampled bar types, e.g. to take other measurements whenever it closes
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum BarKind {
+  Dollar,
+  BaseVolume,
+  Volume,
+  Pnl,
+  Time,
+  Tick,
+  Imbalance,
+  Run
+}
+
+/// Bars closed by a single trade, each sampled on its own trigger
+#[derive(Debug, Default, Clone)]
+pub struct SampledBars {
+  pub dollar_bar: Option<DollarBar>,
+  pub base_volume_bar: Option<BaseVolumeBar>,
+  pub volume_bar: Option<VolumeBar>,
+  pub pnl_bar: Option<PnlBar>,
+  pub time_bars: Vec<TimeBar>,
+  pub tick_bar: Option<InformationBar>,
+  pub imbalance_bar: Option<InformationBar>,
+  pub run_bar: Option<InformationBar>,
+}
+
+impl SampledBars {
+  /// Is Closed
+  /// Whether a bar of the kind closed
+  pub fn is_closed(&self, bar_kind: BarKind) -> bool {
+    match bar_kind {
+      BarKind::Dollar => self.dollar_bar.is_some(),
+      BarKind::BaseVolume => self.base_volume_bar.is_some(),
+      BarKind::Volume => self.volume_bar.is_some(),
+      BarKind::Pnl => self.pnl_bar.is_some(),
+      BarKind::Time => !self.time_bars.is_empty(),
+      BarKind::Tick => self.tick_bar.is_some(),
+      BarKind::Imbalance => self.imbalance_bar.is_some(),
+      BarKind::Run => self.run_bar.is_some()
+    }
+  }
+
+  /// Set Reserves
+  /// Attaches the pool reserves as at each bar's last trade, which for time bars closed by a
+  /// later interval's trade are the reserves from before that trade
+  pub fn set_reserves(&mut self, reserves: Option<&ReservePoint>, time_bar_reserves: Option<&ReservePoint>) {
+    if let Some(bar) = self.dollar_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    if let Some(bar) = self.base_volume_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    if let Some(bar) = self.volume_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    if let Some(bar) = self.pnl_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    for bar in self.time_bars.iter_mut() { bar.reserves = time_bar_reserves.cloned(); }
+    if let Some(bar) = self.tick_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    if let Some(bar) = self.imbalance_bar.as_mut() { bar.reserves = reserves.cloned(); }
+    if let Some(bar) = self.run_bar.as_mut() { bar.reserves = reserves.cloned(); }
+  }
+}
+
+/// Bar Sampler
+/// Runs each configured bar builder over the same trades so every bar type closes independently
+#[derive(Debug, Default, Clone)]
+pub struct BarSampler {
+  pub dollar: Option<DollarBarBuilder>,
+  pub base_volume: Option<BaseVolumeBarBuilder>,
+  pub volume: Option<VolumeBarBuilder>,
+  pub pnl: Option<PnlBarBuilder>,
+  pub time: Option<TimeBarBuilder>,
+  pub tick: Option<TickBarBuilder>,
+  pub imbalance: Option<ImbalanceBarBuilder>,
+  pub run: Option<RunBarBuilder>,
+}
+
+impl BarSampler {
+  pub fn new() -> Self {
+    Self { dollar: None, base_volume: None, volume: None, pnl: None, time: None, tick: None, imbalance: None, run: None }
+  }
+
+  /// Update
+  /// Feeds a trade to every builder and returns the bars it closed
+  pub fn update(&mut self, trade_tx: &TradeTx) -> SampledBars {
+    SampledBars {
+      dollar_bar: self.dollar.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      base_volume_bar: self.base_volume.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      volume_bar: self.volume.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      pnl_bar: self.pnl.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      time_bars: self.time.as_mut().and_then(|builder| builder.sample(trade_tx)).unwrap_or_default(),
+      tick_bar: self.tick.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      imbalance_bar: self.imbalance.as_mut().and_then(|builder| builder.sample(trade_tx)),
+      run_bar: self.run.as_mut().and_then(|builder| builder.sample(trade_tx))
+    }
+  }
+
+  /// Flush
+  /// Closes every builder's partial bar, e.g. at the end of a batch run, and returns them
+  pub fn flush(&mut self) -> SampledBars {
+    SampledBars {
+      dollar_bar: self.dollar.as_mut().and_then(|builder| builder.flush()),
+      base_volume_bar: self.base_volume.as_mut().and_then(|builder| builder.flush()),
+      volume_bar: self.volume.as_mut().and_then(|builder| builder.flush()),
+      pnl_bar: self.pnl.as_mut().and_then(|builder| builder.flush()),
+      time_bars: self.time.as_mut().and_then(|builder| builder.flush()).unwrap_or_default(),
+      tick_bar: self.tick.as_mut().and_then(|builder| builder.flush()),
+      imbalance_bar: self.imbalance.as_mut().and_then(|builder| builder.flush()),
+      run_bar: self.run.as_mut().and_then(|builder| builder.flush())
+    }
+  }
+}
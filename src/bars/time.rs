@@ -0,0 +1,179 @@
+use crate::models::general::{format_block_time, Side, TimeBar, TradeTx};
+use crate::models::traits::BarBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Time Interval
+/// Fixed clock interval a time bar covers
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum TimeInterval {
+  OneSecond,
+  OneMinute,
+  FiveMinutes,
+  OneHour,
+  OneDay
+}
+
+impl TimeInterval {
+  pub fn seconds(&self) -> i64 {
+    match self {
+      TimeInterval::OneSecond => 1,
+      TimeInterval::OneMinute => 60,
+      TimeInterval::FiveMinutes => 300,
+      TimeInterval::OneHour => 3_600,
+      TimeInterval::OneDay => 86_400
+    }
+  }
+
+  /// Bucket Start
+  /// Returns the start of the interval the timestamp falls in
+  pub fn bucket_start(&self, timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(self.seconds())
+  }
+}
+
+/// Time Bar Builder
+/// Buckets trades into fixed clock intervals using the parsed block time
+/// A bar only closes once a trade lands in a later interval, closing every bar it passed, including
+/// empty intervals carried at the previous close when forward fill is on, so the last bar is returned by flush
+#[derive(Debug, Clone)]
+pub struct TimeBarBuilder {
+  pub interval: TimeInterval,
+  pub forward_fill: bool,
+  bar: Option<TimeBar>,
+  closed_bars: Vec<TimeBar>,
+  traders: HashSet<String>,
+}
+
+impl TimeBarBuilder {
+  pub fn new(interval: TimeInterval, forward_fill: bool) -> Self {
+    Self { interval, forward_fill, bar: None, closed_bars: vec![], traders: HashSet::new() }
+  }
+
+  /// Close Bar
+  /// Moves the bar being built, and the empty intervals up to bucket when forward filling, into the closed bars
+  fn close_bar(&mut self, bucket: i64) {
+    let Some(closed_bar) = self.bar.take() else { return };
+    self.traders.clear();
+    let mut empty_bucket: i64 = closed_bar.timestamp + self.interval.seconds();
+    let close: f64 = closed_bar.close;
+    self.closed_bars.push(closed_bar);
+    if self.forward_fill {
+      while empty_bucket < bucket {
+        self.closed_bars.push(Self::empty_bar(empty_bucket, close));
+        empty_bucket += self.interval.seconds();
+      }
+    }
+  }
+
+  fn empty_bar(timestamp: i64, price_quote: f64) -> TimeBar {
+    let mut bar: TimeBar = TimeBar::new();
+    bar.datetime = format_block_time(timestamp);
+    bar.timestamp = timestamp;
+    bar.open = price_quote;
+    bar.high = price_quote;
+    bar.low = price_quote;
+    bar.close = price_quote;
+    bar
+  }
+}
+
+impl BarBuilder for TimeBarBuilder {
+  type Bar = Vec<TimeBar>;
+
+  fn update(&mut self, trade_tx: &TradeTx) {
+    let bucket: i64 = self.interval.bucket_start(trade_tx.block_timestamp);
+    // Trades arrive in block order, so an earlier timestamp still belongs in the open bar
+    if self.bar.as_ref().is_some_and(|bar| bucket > bar.timestamp) { self.close_bar(bucket); }
+
+    let price_quote: f64 = trade_tx.price_quote;
+    let bar: &mut TimeBar = self.bar.get_or_insert_with(|| {
+      let mut bar: TimeBar = TimeBar::new();
+      bar.datetime = format_block_time(bucket);
+      bar.timestamp = bucket;
+      bar.open = price_quote;
+      bar.high = price_quote;
+      bar.low = price_quote;
+      bar
+    });
+    if price_quote > bar.high { bar.high = price_quote; }
+    if price_quote < bar.low { bar.low = price_quote; }
+    bar.close = price_quote;
+    match trade_tx.side {
+      Side::Buy => bar.volume_buys += trade_tx.volume_quote,
+      Side::Sell => bar.volume_sells += trade_tx.volume_quote
+    }
+    bar.trade_count += 1;
+    self.traders.insert(trade_tx.account_addr.clone());
+    bar.unique_traders = self.traders.len() as u64;
+  }
+
+  fn should_close(&self) -> bool {
+    !self.closed_bars.is_empty()
+  }
+
+  fn finish(&self) -> Vec<TimeBar> {
+    self.closed_bars.clone()
+  }
+
+  fn reset(&mut self) {
+    self.closed_bars.clear();
+  }
+
+  fn is_empty(&self) -> bool {
+    self.bar.is_none() && self.closed_bars.is_empty()
+  }
+
+  /// Flush
+  /// Closes the bar currently being built and returns it with any bars not yet sampled
+  fn flush(&mut self) -> Option<Vec<TimeBar>> {
+    if self.is_empty() { return None }
+    self.close_bar(i64::MIN);
+    let bars: Vec<TimeBar> = self.finish();
+    self.reset();
+    Some(bars)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_buckets_trades_into_minutes() {
+    let mut builder: TimeBarBuilder = TimeBarBuilder::new(TimeInterval::OneMinute, false);
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 0.5, 2.0).at_time("2023-12-06T19:54:11Z")).is_none());
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Sell, "0xb", 0.5, 1.0).at_time("2023-12-06T19:54:23Z")).is_none());
+    assert!(builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 0.25, 3.0).at_time("2023-12-06T19:54:59Z")).is_none());
+
+    let closed_bars: Vec<TimeBar> = builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xc", 1.0, 4.0).at_time("2023-12-06T19:57:01Z")).expect("Expected time bar to close");
+    assert_eq!(closed_bars.len(), 1);
+    let bar: &TimeBar = &closed_bars[0];
+    assert_eq!(bar.datetime, "2023-12-06T19:54:00Z");
+    assert_eq!((bar.open, bar.high, bar.low, bar.close), (2.0, 3.0, 1.0, 3.0));
+    assert_eq!(bar.volume_buys, 1.75);
+    assert_eq!(bar.volume_sells, 0.5);
+    assert_eq!(bar.trade_count, 3);
+    assert_eq!(bar.unique_traders, 2);
+
+    let last_bars: Vec<TimeBar> = builder.flush().expect("Open time bar");
+    assert_eq!(last_bars.len(), 1);
+    let last_bar: &TimeBar = &last_bars[0];
+    assert_eq!(last_bar.datetime, "2023-12-06T19:57:00Z");
+    assert_eq!(last_bar.unique_traders, 1);
+    assert!(builder.flush().is_none());
+  }
+
+  #[test]
+  fn it_forward_fills_empty_intervals() {
+    let mut builder: TimeBarBuilder = TimeBarBuilder::new(TimeInterval::OneMinute, true);
+    builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 0.5, 2.0).at_time("2023-12-06T19:54:11Z"));
+    let closed_bars: Vec<TimeBar> = builder.sample(&TradeTx::test_trade(1, Side::Buy, "0xa", 1.0, 4.0).at_time("2023-12-06T19:57:01Z")).expect("Expected time bars to close");
+
+    let datetimes: Vec<&str> = closed_bars.iter().map(|bar| bar.datetime.as_str()).collect();
+    assert_eq!(datetimes, vec!["2023-12-06T19:54:00Z", "2023-12-06T19:55:00Z", "2023-12-06T19:56:00Z"]);
+    assert_eq!((closed_bars[2].open, closed_bars[2].close), (2.0, 2.0));
+    assert_eq!(closed_bars[2].trade_count, 0);
+    assert_eq!(closed_bars[2].volume_buys, 0.0);
+  }
+}
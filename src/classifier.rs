@@ -0,0 +1,119 @@
+use crate::models::general::{BotReason, Side, TradeTx};
+use std::collections::{BTreeSet, HashMap};
+
+/// Joins reason codes into a single report field, e.g. EARLY|PROXY
+pub fn reason_codes(reasons: &[BotReason]) -> String {
+  reasons.iter().map(|reason| reason.code()).collect::<Vec<&str>>().join("|")
+}
+
+/// Whether any of the reasons marks an address as a bot
+pub fn is_bot(reasons: &[BotReason]) -> bool {
+  reasons.iter().any(|reason| reason.is_bot_evidence())
+}
+
+/// Bot Classifier
+/// Flags addresses that buy within early_blocks of launch, buy in the liquidity add block,
+/// trade at least multi_trade_threshold times in one block, or buy with a tx sender other than the trade's buyer
+/// The last is only reported alongside the others and never flags an address on its own
+/// Trades must arrive in block order, as they do from every data source
+#[derive(Debug, Clone)]
+pub struct BotClassifier {
+  pub early_blocks: u64,
+  pub multi_trade_threshold: u64,
+  pub launch_block: Option<u64>,
+  pub liquidity_add_block: Option<u64>,
+  pub reasons: HashMap<String, BTreeSet<BotReason>>,
+  current_block: u64,
+  block_trade_counts: HashMap<String, u64>,
+}
+
+impl Default for BotClassifier {
+  fn default() -> Self {
+    Self::new(0, u64::MAX)
+  }
+}
+
+impl BotClassifier {
+  pub fn new(early_blocks: u64, multi_trade_threshold: u64) -> Self {
+    Self {
+      early_blocks,
+      multi_trade_threshold,
+      launch_block: None,
+      liquidity_add_block: None,
+      reasons: HashMap::new(),
+      current_block: 0,
+      block_trade_counts: HashMap::new()
+    }
+  }
+
+  /// Observe
+  /// Classifies a trade and returns every reason its address has been flagged for so far
+  pub fn observe(&mut self, trade_tx: &TradeTx) -> Vec<BotReason> {
+    let launch_block: u64 = *self.launch_block.get_or_insert(trade_tx.block_num);
+    if trade_tx.block_num != self.current_block {
+      self.current_block = trade_tx.block_num;
+      self.block_trade_counts.clear();
+    }
+    let block_trades: &mut u64 = self.block_trade_counts.entry(trade_tx.account_addr.clone()).or_insert(0);
+    *block_trades += 1;
+
+    let mut new_reasons: Vec<BotReason> = vec![];
+    if *block_trades >= self.multi_trade_threshold { new_reasons.push(BotReason::MultiTradeBlock); }
+    if trade_tx.side == Side::Buy {
+      if trade_tx.block_num < launch_block + self.early_blocks { new_reasons.push(BotReason::EarlyBlockBuyer); }
+      if self.liquidity_add_block == Some(trade_tx.block_num) { new_reasons.push(BotReason::LiquidityBlockBuyer); }
+      if !trade_tx.trade_addr.is_empty() && !trade_tx.trade_addr.eq_ignore_ascii_case(&trade_tx.account_addr) {
+        new_reasons.push(BotReason::ProxiedTrade);
+      }
+    }
+
+    let reasons: &mut BTreeSet<BotReason> = self.reasons.entry(trade_tx.account_addr.clone()).or_default();
+    reasons.extend(new_reasons);
+    reasons.iter().copied().collect()
+  }
+
+  /// Reasons For
+  /// Returns the reasons an address has been flagged for, empty if it has not been
+  pub fn reasons_for(&self, address: &str) -> Vec<BotReason> {
+    self.reasons.get(address).map(|reasons| reasons.iter().copied().collect()).unwrap_or_default()
+  }
+
+  pub fn is_flagged(&self, address: &str) -> bool {
+    self.reasons.get(address).is_some_and(|reasons| reasons.iter().any(|reason| reason.is_bot_evidence()))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn trade_tx(block_num: u64, side: Side, account: &str, trade_addr: &str) -> TradeTx {
+    let mut trade_tx: TradeTx = TradeTx::test_trade(block_num, side, account, 0.0, 0.0);
+    trade_tx.trade_addr = trade_addr.to_string();
+    trade_tx
+  }
+
+  #[test]
+  fn it_flags_snipers_and_bots() {
+    let mut classifier: BotClassifier = BotClassifier::new(2, 3);
+    classifier.launch_block = Some(100);
+    classifier.liquidity_add_block = Some(100);
+
+    assert_eq!(classifier.observe(&trade_tx(100, Side::Buy, "0xa", "0xA")), vec![BotReason::EarlyBlockBuyer, BotReason::LiquidityBlockBuyer]);
+    assert_eq!(classifier.observe(&trade_tx(101, Side::Buy, "0xb", "0xrouter")), vec![BotReason::EarlyBlockBuyer, BotReason::ProxiedTrade]);
+    assert!(classifier.observe(&trade_tx(102, Side::Buy, "0xc", "0xc")).is_empty());
+
+    // Sells are never early buys, but still count towards trades in a block
+    classifier.observe(&trade_tx(110, Side::Sell, "0xc", "0xpool"));
+    classifier.observe(&trade_tx(110, Side::Buy, "0xc", "0xc"));
+    assert_eq!(classifier.observe(&trade_tx(110, Side::Sell, "0xc", "0xpool")), vec![BotReason::MultiTradeBlock]);
+    assert!(classifier.is_flagged("0xc"));
+    assert!(!classifier.is_flagged("0xd"));
+    assert_eq!(reason_codes(&classifier.reasons_for("0xb")), "EARLY|PROXY");
+
+    // Sending a buy to another recipient is reported but does not make a bot on its own
+    assert_eq!(classifier.observe(&trade_tx(120, Side::Buy, "0xe", "0xrecipient")), vec![BotReason::ProxiedTrade]);
+    assert!(!classifier.is_flagged("0xe"));
+    assert!(!is_bot(&classifier.reasons_for("0xe")));
+  }
+}
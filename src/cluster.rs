@@ -0,0 +1,312 @@
+use crate::costbasis::ExternalCostBasisResolver;
+use crate::models::address::{AddressRecords, CostBasisMethod};
+use crate::models::general::{ClusterReason, FundingTransfer, Side, TradeTx, WalletCluster};
+use csv::Reader;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+
+/// Load Funding Transfers Csv
+/// Loads transfers from a csv with from, to, block_num and amount_eth columns
+pub fn load_funding_transfers_csv(file_path: &str) -> Result<Vec<FundingTransfer>, Box<dyn Error>> {
+  let file = File::open(file_path)?;
+  let mut rdr = Reader::from_reader(file);
+  let mut transfers: Vec<FundingTransfer> = vec![];
+  for result in rdr.deserialize::<FundingTransfer>() {
+    transfers.push(result?);
+  }
+  Ok(transfers)
+}
+
+/// Load Funding Transfers Json
+/// Loads transfers from a json array of from, to, block_num and amount_eth objects
+pub fn load_funding_transfers_json(file_path: &str) -> Result<Vec<FundingTransfer>, Box<dyn Error>> {
+  let data: String = std::fs::read_to_string(file_path)?;
+  Ok(serde_json::from_str(&data)?)
+}
+
+#[derive(Debug, Default, Clone)]
+struct PairEvidence {
+  co_timed: u64,
+  matching_sizes: u64,
+}
+
+#[derive(Debug, Clone)]
+struct RecentTrade {
+  block_num: u64,
+  account_addr: String,
+  side: Side,
+  volume_quote: f64,
+}
+
+/// Wallet Clusterer
+/// Links addresses funded by the same parent, addresses that traded the same side within timing_blocks
+/// of each other at least min_co_trades times, and addresses that did so with quote sizes within
+/// size_tolerance of each other at least min_matching_sizes times
+/// Funders of more than max_funder_children addresses are taken to be exchanges or routers and ignored
+/// Co-timed trading alone never grows a cluster past max_co_timed_cluster addresses, since busy traders
+/// share blocks by chance and transitive links would otherwise collapse them into one entity
+/// Trades must arrive in block order, as they do from every data source
+#[derive(Debug, Clone)]
+pub struct WalletClusterer {
+  pub timing_blocks: u64,
+  pub min_co_trades: u64,
+  pub size_tolerance: f64,
+  pub min_matching_sizes: u64,
+  pub max_funder_children: usize,
+  pub max_co_timed_cluster: usize,
+  funders: HashMap<String, String>,
+  pairs: HashMap<(String, String), PairEvidence>,
+  addresses: BTreeSet<String>,
+  recent: VecDeque<RecentTrade>,
+}
+
+impl Default for WalletClusterer {
+  fn default() -> Self {
+    Self::new(0, u64::MAX, 0.0, u64::MAX)
+  }
+}
+
+impl WalletClusterer {
+  pub fn new(timing_blocks: u64, min_co_trades: u64, size_tolerance: f64, min_matching_sizes: u64) -> Self {
+    Self {
+      timing_blocks,
+      min_co_trades,
+      size_tolerance,
+      min_matching_sizes,
+      max_funder_children: 50,
+      max_co_timed_cluster: 5,
+      funders: HashMap::new(),
+      pairs: HashMap::new(),
+      addresses: BTreeSet::new(),
+      recent: VecDeque::new()
+    }
+  }
+
+  /// Add Funding Transfers
+  /// Records each address's funding parent, keeping the earliest transfer into it
+  pub fn add_funding_transfers(&mut self, transfers: &[FundingTransfer]) {
+    let mut ordered: Vec<&FundingTransfer> = transfers.iter().collect();
+    ordered.sort_by_key(|transfer| transfer.block_num);
+    for transfer in ordered {
+      self.funders.entry(transfer.to.to_lowercase()).or_insert_with(|| transfer.from.to_lowercase());
+    }
+  }
+
+  /// Observe
+  /// Records the trade's timing and size against other addresses' recent trades
+  pub fn observe(&mut self, trade_tx: &TradeTx) {
+    let account_addr: String = trade_tx.account_addr.to_lowercase();
+    let block_num: u64 = trade_tx.block_num;
+    while self.recent.front().is_some_and(|recent| recent.block_num + self.timing_blocks < block_num) {
+      self.recent.pop_front();
+    }
+
+    // One piece of evidence per address pair per trade
+    let mut linked: HashMap<(String, String), bool> = HashMap::new();
+    for recent in self.recent.iter().filter(|recent| recent.side == trade_tx.side && recent.account_addr != account_addr) {
+      let largest: f64 = recent.volume_quote.abs().max(trade_tx.volume_quote.abs());
+      let is_matching_size: bool = largest > 0.0 && (recent.volume_quote - trade_tx.volume_quote).abs() / largest <= self.size_tolerance;
+      let pair: (String, String) = if recent.account_addr < account_addr { (recent.account_addr.clone(), account_addr.clone()) } else { (account_addr.clone(), recent.account_addr.clone()) };
+      *linked.entry(pair).or_default() |= is_matching_size;
+    }
+    for (pair, is_matching_size) in linked {
+      let evidence: &mut PairEvidence = self.pairs.entry(pair).or_default();
+      evidence.co_timed += 1;
+      if is_matching_size { evidence.matching_sizes += 1; }
+    }
+
+    self.addresses.insert(account_addr.clone());
+    self.recent.push_back(RecentTrade { block_num, account_addr, side: trade_tx.side.clone(), volume_quote: trade_tx.volume_quote });
+  }
+
+  /// Cluster Map
+  /// Maps every traded address to its cluster id, its own address when it was not linked to any other
+  pub fn cluster_map(&self) -> HashMap<String, String> {
+    self.link().0
+  }
+
+  /// Clusters
+  /// Returns the clusters of more than one address, ordered by cluster id
+  pub fn clusters(&self) -> Vec<WalletCluster> {
+    let (cluster_map, reasons) = self.link();
+    let mut members: HashMap<String, Vec<String>> = HashMap::new();
+    for (address, cluster_id) in cluster_map.iter() {
+      members.entry(cluster_id.clone()).or_default().push(address.clone());
+    }
+    let mut clusters: Vec<WalletCluster> = members.into_iter()
+      .filter(|(_, addresses)| addresses.len() > 1)
+      .map(|(cluster_id, mut addresses)| {
+        addresses.sort();
+        let funders: BTreeSet<String> = addresses.iter().filter_map(|address| self.funders.get(address).cloned()).collect();
+        let cluster_reasons: Vec<&str> = reasons.get(&cluster_id).map(|reasons| reasons.iter().map(|reason| reason.code()).collect()).unwrap_or_default();
+        WalletCluster { reasons: cluster_reasons.join("|"), cluster_id, addresses, funders: funders.into_iter().collect() }
+      })
+      .collect();
+    clusters.sort_by(|a, b| a.cluster_id.cmp(&b.cluster_id));
+    clusters
+  }
+
+  /// Unions the linked addresses, returning each address's cluster id and the reasons behind each cluster
+  fn link(&self) -> (HashMap<String, String>, HashMap<String, BTreeSet<ClusterReason>>) {
+    let mut parents: HashMap<String, String> = self.addresses.iter().map(|address| (address.clone(), address.clone())).collect();
+    let mut links: Vec<(String, String, ClusterReason)> = vec![];
+
+    let mut children: HashMap<&String, Vec<&String>> = HashMap::new();
+    for (address, funder) in self.funders.iter().filter(|(address, _)| self.addresses.contains(*address)) {
+      children.entry(funder).or_default().push(address);
+    }
+    for (funder, funded) in children.iter().filter(|(_, funded)| funded.len() <= self.max_funder_children) {
+      for address in funded.iter() {
+        links.push(((*funded[0]).clone(), (*address).clone(), ClusterReason::SharedFunder));
+      }
+      // A funder that also traded belongs to the entity it funded
+      if self.addresses.contains(*funder) { links.push(((*funder).clone(), (*funded[0]).clone(), ClusterReason::SharedFunder)); }
+    }
+    for ((a, b), evidence) in self.pairs.iter() {
+      if evidence.matching_sizes >= self.min_matching_sizes { links.push((a.clone(), b.clone(), ClusterReason::MatchingSizes)); }
+    }
+    // Timing links go last and in a fixed order, so the size cap sees the clusters the stronger evidence built
+    let mut co_timed: Vec<(&String, &String)> = self.pairs.iter()
+      .filter(|(_, evidence)| evidence.co_timed >= self.min_co_trades)
+      .map(|((a, b), _)| (a, b))
+      .collect();
+    co_timed.sort();
+    links.extend(co_timed.into_iter().map(|(a, b)| (a.clone(), b.clone(), ClusterReason::CoTimedTrading)));
+
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    let mut applied: Vec<(String, String, ClusterReason)> = vec![];
+    for (a, b, reason) in links {
+      let root_a: String = find_root(&mut parents, &a);
+      let root_b: String = find_root(&mut parents, &b);
+      if root_a != root_b {
+        let size_a: usize = sizes.get(&root_a).copied().unwrap_or(1);
+        let size_b: usize = sizes.get(&root_b).copied().unwrap_or(1);
+        if reason == ClusterReason::CoTimedTrading && size_a + size_b > self.max_co_timed_cluster { continue; }
+        // The lowest address is the root so cluster ids are stable
+        let (root, child): (String, String) = if root_a < root_b { (root_a, root_b) } else { (root_b, root_a) };
+        sizes.insert(root.clone(), size_a + size_b);
+        parents.insert(child, root);
+      }
+      applied.push((a, b, reason));
+    }
+
+    let addresses: Vec<String> = parents.keys().cloned().collect();
+    let cluster_map: HashMap<String, String> = addresses.into_iter().map(|address| {
+      let root: String = find_root(&mut parents, &address);
+      (address, root)
+    }).collect();
+    let mut reasons: HashMap<String, BTreeSet<ClusterReason>> = HashMap::new();
+    for (a, b, reason) in applied {
+      if a != b { reasons.entry(cluster_map[&a].clone()).or_default().insert(reason); }
+    }
+    (cluster_map, reasons)
+  }
+}
+
+fn find_root(parents: &mut HashMap<String, String>, address: &str) -> String {
+  let parent: String = parents.entry(address.to_string()).or_insert_with(|| address.to_string()).clone();
+  if parent == address { return parent; }
+  let root: String = find_root(parents, &parent);
+  parents.insert(address.to_string(), root.clone());
+  root
+}
+
+/// Entity Records
+/// Re-runs the address records over the trades with each trader replaced by its cluster,
+/// so tokens bought by one wallet and sold by another in the same entity close against each other
+/// Only base sold beyond the entity's own open interest is costed through the external cost basis resolver
+pub fn entity_records(trades: &[TradeTx], cluster_map: &HashMap<String, String>, cost_basis_method: CostBasisMethod, external_cost_basis: &ExternalCostBasisResolver) -> HashMap<String, AddressRecords> {
+  let mut records_hm: HashMap<String, AddressRecords> = HashMap::new();
+  for trade_tx in trades {
+    let account_addr: String = trade_tx.account_addr.to_lowercase();
+    let cluster_id: String = cluster_map.get(&account_addr).cloned().unwrap_or(account_addr);
+    let record: &mut AddressRecords = records_hm.entry(cluster_id).or_insert_with(|| AddressRecords::with_cost_basis(cost_basis_method));
+    let external_cost_quote: f64 = if trade_tx.side == Side::Sell && trade_tx.volume_base > record.get_open_interest() {
+      external_cost_basis.resolve(&trade_tx.account_addr, trade_tx.price_quote).1
+    } else {
+      0.0
+    };
+    record.record_trade(trade_tx);
+    match trade_tx.side {
+      Side::Buy => record.open_position_from(trade_tx),
+      Side::Sell => { record.close_positions_from(trade_tx, external_cost_quote); }
+    }
+  }
+  records_hm
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::models::general::ExternalCostBasis;
+
+  #[test]
+  fn it_clusters_by_funder_timing_and_size() {
+    let mut clusterer: WalletClusterer = WalletClusterer::new(1, 3, 0.01, 2);
+    clusterer.add_funding_transfers(&[
+      FundingTransfer { from: "0xF".to_string(), to: "0xa".to_string(), block_num: 5, amount_eth: 1.0 },
+      FundingTransfer { from: "0xf".to_string(), to: "0xb".to_string(), block_num: 6, amount_eth: 1.0 },
+      FundingTransfer { from: "0xother".to_string(), to: "0xb".to_string(), block_num: 9, amount_eth: 1.0 }
+    ]);
+    // 0xa and 0xb share a funder, 0xc and 0xd buy matching sizes in the same blocks twice
+    for (block_num, account, volume_base) in [(10, "0xa", 1.0), (20, "0xb", 3.0), (30, "0xc", 5.0), (30, "0xd", 5.0), (40, "0xc", 2.0), (41, "0xd", 2.0), (60, "0xe", 2.0)] {
+      clusterer.observe(&TradeTx::test_trade(block_num, Side::Buy, account, volume_base, 1.0));
+    }
+
+    let clusters: Vec<WalletCluster> = clusterer.clusters();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!((clusters[0].addresses.clone(), clusters[0].reasons.as_str()), (vec!["0xa".to_string(), "0xb".to_string()], "FUNDER"));
+    assert_eq!(clusters[0].funders, vec!["0xf".to_string()]);
+    assert_eq!((clusters[1].addresses.clone(), clusters[1].reasons.as_str()), (vec!["0xc".to_string(), "0xd".to_string()], "SIZES"));
+    assert_eq!(clusterer.cluster_map()["0xe"], "0xe");
+  }
+
+  #[test]
+  fn it_caps_clusters_linked_only_by_timing() {
+    let mut clusterer: WalletClusterer = WalletClusterer::new(0, 2, 0.0, u64::MAX);
+    clusterer.max_co_timed_cluster = 2;
+    // Three unrelated wallets trade in the same blocks with different sizes
+    for block_num in [10, 20] {
+      for (account, volume_base) in [("0xa", 1.0), ("0xb", 2.0), ("0xc", 3.0)] {
+        clusterer.observe(&TradeTx::test_trade(block_num, Side::Buy, account, volume_base, 1.0));
+      }
+    }
+
+    let clusters: Vec<WalletCluster> = clusterer.clusters();
+    assert_eq!(clusters.len(), 1);
+    assert_eq!((clusters[0].addresses.clone(), clusters[0].reasons.as_str()), (vec!["0xa".to_string(), "0xb".to_string()], "CO_TIMED"));
+    assert_eq!(clusterer.cluster_map()["0xc"], "0xc");
+  }
+
+  #[test]
+  fn it_reruns_pnl_at_entity_level() {
+    let trades: Vec<TradeTx> = vec![TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0), TradeTx::test_trade(2, Side::Sell, "0xb", 10.0, 3.0)];
+    let cluster_map: HashMap<String, String> = HashMap::from([("0xa".to_string(), "0xa".to_string()), ("0xb".to_string(), "0xa".to_string())]);
+    let resolver: ExternalCostBasisResolver = ExternalCostBasisResolver::new(ExternalCostBasis::Zero);
+    let records_hm: HashMap<String, AddressRecords> = entity_records(&trades, &cluster_map, CostBasisMethod::Fifo, &resolver);
+    assert_eq!(records_hm.len(), 1);
+    // Wallet level this is a 30 external gain for 0xb, entity level a 20 internal gain
+    assert_eq!(records_hm["0xa"].realized_internal_pnl, 20.0);
+    assert_eq!(records_hm["0xa"].realized_external_pnl, 0.0);
+  }
+
+  #[test]
+  fn it_costs_entity_oversells_against_entity_open_interest() {
+    let mut oversell: TradeTx = TradeTx::test_trade(2, Side::Sell, "0xb", 15.0, 3.0);
+    // 0xb's own wallet-level cost, which should not carry over to the entity
+    oversell.external_cost_quote = 3.0;
+    let trades: Vec<TradeTx> = vec![TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0), oversell];
+    let cluster_map: HashMap<String, String> = HashMap::from([("0xa".to_string(), "0xa".to_string()), ("0xb".to_string(), "0xa".to_string())]);
+
+    // The entity held 10, so only the other 5 are external and cost nothing under a zero basis
+    let resolver: ExternalCostBasisResolver = ExternalCostBasisResolver::new(ExternalCostBasis::Zero);
+    let records_hm: HashMap<String, AddressRecords> = entity_records(&trades, &cluster_map, CostBasisMethod::Fifo, &resolver);
+    assert_eq!(records_hm["0xa"].realized_internal_pnl, 20.0);
+    assert_eq!(records_hm["0xa"].realized_external_pnl, 15.0);
+
+    let resolver: ExternalCostBasisResolver = ExternalCostBasisResolver::new(ExternalCostBasis::SellPrice);
+    let records_hm: HashMap<String, AddressRecords> = entity_records(&trades, &cluster_map, CostBasisMethod::Fifo, &resolver);
+    assert_eq!(records_hm["0xa"].realized_external_pnl, 0.0);
+  }
+}
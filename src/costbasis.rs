@@ -0,0 +1,112 @@
+use crate::models::general::ExternalCostBasis;
+use csv::Reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+
+/// A transfer of base tokens into an address from outside the pool, priced in quote
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImportedTransfer {
+  pub address: String,
+  pub amount_base: f64,
+  pub price_quote: f64,
+}
+
+/// External Cost Basis Resolver
+/// Prices tokens an address sells without having bought them in the pool
+#[derive(Debug, Default, Clone)]
+pub struct ExternalCostBasisResolver {
+  pub method: ExternalCostBasis,
+  first_seen_price: Option<f64>,
+  imported_costs: HashMap<String, f64>,
+}
+
+impl ExternalCostBasisResolver {
+  pub fn new(method: ExternalCostBasis) -> Self {
+    Self { method, first_seen_price: None, imported_costs: HashMap::new() }
+  }
+
+  /// With Transfers
+  /// Creates an imported resolver costing each address at the weighted average price of its transfers
+  pub fn with_transfers(transfers: &[ImportedTransfer]) -> Self {
+    let mut totals: HashMap<String, (f64, f64)> = HashMap::new();
+    for transfer in transfers {
+      let (amount_base, cost_quote) = totals.entry(transfer.address.to_lowercase()).or_default();
+      *amount_base += transfer.amount_base;
+      *cost_quote += transfer.amount_base * transfer.price_quote;
+    }
+    let imported_costs: HashMap<String, f64> = totals.into_iter()
+      .filter(|(_, (amount_base, _))| *amount_base > 0.0)
+      .map(|(address, (amount_base, cost_quote))| (address, cost_quote / amount_base))
+      .collect();
+    Self { method: ExternalCostBasis::Imported, first_seen_price: None, imported_costs }
+  }
+
+  /// From Csv
+  /// Loads transfers from a csv with address, amount_base and price_quote columns
+  pub fn from_csv(file_path: &str) -> Result<Self, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let mut rdr = Reader::from_reader(file);
+    let mut transfers: Vec<ImportedTransfer> = vec![];
+    for result in rdr.deserialize::<ImportedTransfer>() {
+      transfers.push(result?);
+    }
+    Ok(Self::with_transfers(&transfers))
+  }
+
+  /// From Json
+  /// Loads transfers from a json array of address, amount_base and price_quote objects
+  pub fn from_json(file_path: &str) -> Result<Self, Box<dyn Error>> {
+    let data: String = std::fs::read_to_string(file_path)?;
+    let transfers: Vec<ImportedTransfer> = serde_json::from_str(&data)?;
+    Ok(Self::with_transfers(&transfers))
+  }
+
+  /// Observe
+  /// Records a pool price so the first one seen can be used as a cost basis
+  pub fn observe(&mut self, price_quote: f64) {
+    if self.first_seen_price.is_none() { self.first_seen_price = Some(price_quote); }
+  }
+
+  /// Resolve
+  /// Returns the method actually applied and the per unit cost for an address selling at sell_price_quote
+  /// Addresses missing from the imported transfers fall back to a zero cost basis
+  pub fn resolve(&self, address: &str, sell_price_quote: f64) -> (ExternalCostBasis, f64) {
+    match self.method {
+      ExternalCostBasis::Zero => (ExternalCostBasis::Zero, 0.0),
+      ExternalCostBasis::FirstSeenPrice => (ExternalCostBasis::FirstSeenPrice, self.first_seen_price.unwrap_or(sell_price_quote)),
+      ExternalCostBasis::SellPrice => (ExternalCostBasis::SellPrice, sell_price_quote),
+      ExternalCostBasis::Imported => match self.imported_costs.get(&address.to_lowercase()) {
+        Some(cost_quote) => (ExternalCostBasis::Imported, *cost_quote),
+        None => (ExternalCostBasis::Zero, 0.0)
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_resolves_external_cost_basis() {
+    let mut resolver: ExternalCostBasisResolver = ExternalCostBasisResolver::new(ExternalCostBasis::FirstSeenPrice);
+    assert_eq!(resolver.resolve("0xa", 3.0), (ExternalCostBasis::FirstSeenPrice, 3.0));
+    resolver.observe(1.0);
+    resolver.observe(2.0);
+    assert_eq!(resolver.resolve("0xa", 3.0), (ExternalCostBasis::FirstSeenPrice, 1.0));
+    assert_eq!(ExternalCostBasisResolver::new(ExternalCostBasis::SellPrice).resolve("0xa", 3.0), (ExternalCostBasis::SellPrice, 3.0));
+  }
+
+  #[test]
+  fn it_averages_imported_transfers() {
+    let transfers: Vec<ImportedTransfer> = serde_json::from_str(r#"[
+      { "address": "0xA", "amount_base": 10.0, "price_quote": 1.0 },
+      { "address": "0xa", "amount_base": 30.0, "price_quote": 3.0 }
+    ]"#).expect("Failed to parse transfers");
+    let resolver: ExternalCostBasisResolver = ExternalCostBasisResolver::with_transfers(&transfers);
+    assert_eq!(resolver.resolve("0xa", 5.0), (ExternalCostBasis::Imported, 2.5));
+    assert_eq!(resolver.resolve("0xb", 5.0), (ExternalCostBasis::Zero, 0.0));
+  }
+}
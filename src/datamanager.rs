@@ -1,8 +1,10 @@
 use crate::WORKING_DIR;
-use crate::models::bitquery::{BitQueryData, TradeInfo};
+use crate::models::bitquery::{BitQueryData, EVMData, TradeInfo};
 use reqwest::Client;
 use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::future::Future;
 use std::path::Path;
 use std::io::Write;
 
@@ -13,6 +15,8 @@ pub struct QueryVariables {
   pub offset: i32,
   pub token: String,
   pub pool: String,
+  pub from_block: String,
+  pub till_block: String,
 }
 
 #[derive(Serialize)]
@@ -35,26 +39,36 @@ impl DataManager {
         limit,
         offset,
         token: token.to_string(),
-        pool: pool.to_string()
+        pool: pool.to_string(),
+        from_block: "0".to_string(),
+        till_block: u64::MAX.to_string()
       },
       dex_trade_data: None
     }
   }
 
-  /// Get Trade Data
-  /// Fetches trade data from BitQuery
-  async fn get_trade_data(&self) -> Result<BitQueryData, reqwest::Error> {
+  /// Set Block Range
+  /// Restricts fetching to trades between from_block and till_block (inclusive)
+  pub fn set_block_range(&mut self, from_block: u64, till_block: u64) {
+    self.query_vars.from_block = from_block.to_string();
+    self.query_vars.till_block = till_block.to_string();
+  }
+
+  /// Get Trade Data Page
+  /// Fetches a single page of trade data from BitQuery, authenticating with the BITQUERY_API_KEY environment variable
+  async fn get_trade_data_page(&self, query_vars: &QueryVariables) -> Result<BitQueryData, Box<dyn std::error::Error>> {
+    let api_key: String = std::env::var("BITQUERY_API_KEY").map_err(|_| "BITQUERY_API_KEY must be set to a BitQuery API key")?;
     let client = Client::new();
     let graphql_url = "https://streaming.bitquery.io/graphql";
   
     let query = GraphQLQuery {
       query: "
-        query ($network: evm_network, $limit: Int!, $token: String!, $pool: String!) {
+        query ($network: evm_network, $limit: Int!, $offset: Int!, $token: String!, $pool: String!, $from_block: String!, $till_block: String!) {
           EVM(network: $network, dataset: combined) {
             DEXTradeByTokens(
               orderBy: {descending: Block_Number}
-              limit: {count: $limit}
-              where: {Trade: {Currency: {SmartContract: {is: $token}, ProtocolName: {}}, Dex: {Pair: {SmartContract: {is: $pool}}}}}
+              limit: {count: $limit, offset: $offset}
+              where: {Block: {Number: {ge: $from_block, le: $till_block}}, Trade: {Currency: {SmartContract: {is: $token}, ProtocolName: {}}, Dex: {Pair: {SmartContract: {is: $pool}}}}}
             ) {
               ChainId
               Block {
@@ -85,16 +99,21 @@ impl DataManager {
                 Hash
                 From
                 Index
+                Gas
+                GasPrice
+              }
+              Log {
+                Index
               }
             }
           }
         }
       ".to_string(),
-      variables: self.query_vars.clone(),
+      variables: query_vars.clone(),
     };
   
     let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("X-API-KEY", "BQYPxLzWNw6hBdlhegCZWhkEnN5DNH9o".parse().unwrap());
+    headers.insert("X-API-KEY", api_key.parse()?);
     headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
   
     let response = client
@@ -109,33 +128,150 @@ impl DataManager {
     Ok(response)
   }
 
+  /// Get Trade Data
+  /// Fetches every BitQuery page of trades in the query's block range
+  /// Returns de-duplicated trades in ascending block order
+  async fn get_trade_data(&self, query_vars: &QueryVariables) -> Result<Vec<TradeInfo>, Box<dyn std::error::Error>> {
+    page_trade_data(query_vars, |page_vars| async move { self.get_trade_data_page(&page_vars).await }).await
+  }
+
   /// Get or Create Data
-  /// Retrieves data if not exists otherwise loads it
+  /// Loads cached data and refreshes it with any trades from the last cached block onwards
+  /// Fetches the full history if no cache exists
+  /// A failed refresh returns its error rather than running on a cache that may be stale
   pub async fn load_or_get_new_trade_data(&mut self) -> Result<(), Box<dyn std::error::Error>> {
     let file_path: String = format!("{}/{}.json", WORKING_DIR, self.query_vars.pool);
-    let trades_data: BitQueryData = if Path::new(file_path.as_str()).exists() {
+    let cached_data: Option<BitQueryData> = if Path::new(file_path.as_str()).exists() {
       println!("loading data...");
-      let data_text: String = fs::read_to_string(file_path).expect("Could not read file");
-      serde_json::from_str::<BitQueryData>(&data_text).expect("Failed to read data from file")
+      let data_text: String = fs::read_to_string(&file_path).expect("Could not read file");
+      let cached_data: BitQueryData = serde_json::from_str::<BitQueryData>(&data_text).expect("Failed to read data from file");
+
+      // Caches saved without log indexes cannot be merged by trade key so they are refetched in full
+      if cached_data.has_log_indexes() {
+        Some(cached_data)
+      } else {
+        println!("cached data has no log indexes, refetching...");
+        None
+      }
     } else {
-      println!("fetching data...");
-      let trade_data = self.get_trade_data().await?;
-      let td_text: String = serde_json::to_string_pretty(&trade_data).expect("Failed to convert data to string");
-      let mut file_save = fs::File::create(file_path).expect("Unable to create file");
-      file_save.write_all(td_text.as_bytes()).expect("Unable to write data");
-      trade_data
+      None
+    };
+
+    let trades_data: BitQueryData = match cached_data {
+      Some(cached_data) => {
+        // Re-request the last cached block as it may have been only partially indexed when cached
+        let last_block: u64 = cached_data.highest_block();
+        let configured_from: u64 = self.query_vars.from_block.parse::<u64>()?;
+        let mut refresh_vars: QueryVariables = self.query_vars.clone();
+        refresh_vars.from_block = last_block.max(configured_from).to_string();
+
+        println!("refreshing data from block {}...", refresh_vars.from_block);
+        let new_trades: Vec<TradeInfo> = self.get_trade_data(&refresh_vars).await
+          .map_err(|e| format!("Failed to refresh cached data from block {}: {}", refresh_vars.from_block, e))?;
+        let merged_data: BitQueryData = merge_trade_data(cached_data, new_trades);
+        save_trade_data(&file_path, &merged_data)?;
+        merged_data
+      },
+      None => {
+        println!("fetching data...");
+        let trades: Vec<TradeInfo> = self.get_trade_data(&self.query_vars).await?;
+        let trade_data: BitQueryData = merge_trade_data(BitQueryData::new(), trades);
+        save_trade_data(&file_path, &trade_data)?;
+        trade_data
+      }
     };
 
     let Some(trades) = trades_data.data.get("EVM") else { panic!("No EVM data was found") };
-    let trades_reversed: Vec<TradeInfo> = trades.dex_trade_by_tokens.iter().rev().cloned().collect();
-    self.dex_trade_data = Some(trades_reversed);
+    self.dex_trade_data = Some(order_trades(trades.dex_trade_by_tokens.clone()));
     Ok(())
   }
 }
 
+/// Merge Trade Data
+/// Merges newly fetched trades (ascending) into cached data without duplicates
+/// Trades are stored in BitQuery's descending order and the highest block is recorded
+pub fn merge_trade_data(cached_data: BitQueryData, new_trades: Vec<TradeInfo>) -> BitQueryData {
+  let cached_trades: Vec<TradeInfo> = match cached_data.data.get("EVM") {
+    Some(evm_data) => evm_data.dex_trade_by_tokens.clone(),
+    None => vec![]
+  };
+  let combined: Vec<TradeInfo> = new_trades.into_iter().rev().chain(cached_trades).collect();
+  let trades_descending: Vec<TradeInfo> = order_trades(combined).into_iter().rev().collect();
+  let mut merged_data: BitQueryData = BitQueryData {
+    data: HashMap::from([("EVM".to_string(), EVMData { dex_trade_by_tokens: trades_descending })]),
+    last_block: 0
+  };
+  merged_data.last_block = merged_data.highest_block();
+  merged_data
+}
+
+/// Save Trade Data
+/// Writes to a temporary file then renames it over the cache so a failed write never leaves a truncated cache
+fn save_trade_data(file_path: &str, trade_data: &BitQueryData) -> Result<(), Box<dyn std::error::Error>> {
+  let tmp_path: String = format!("{}.tmp", file_path);
+  let td_text: String = serde_json::to_string_pretty(trade_data)?;
+  let mut file_save = fs::File::create(&tmp_path)?;
+  file_save.write_all(td_text.as_bytes())?;
+  file_save.sync_all()?;
+  fs::rename(&tmp_path, file_path)?;
+  Ok(())
+}
+
+/// Page Trade Data
+/// Walks pages from get_page backwards from till_block using a Block_Number cursor until the
+/// pool history (or configured block range) is exhausted
+/// Returns de-duplicated trades in ascending block order
+async fn page_trade_data<F, Fut>(query_vars: &QueryVariables, mut get_page: F) -> Result<Vec<TradeInfo>, Box<dyn std::error::Error>>
+where
+  F: FnMut(QueryVariables) -> Fut,
+  Fut: Future<Output = Result<BitQueryData, Box<dyn std::error::Error>>>
+{
+  let from_block: u64 = query_vars.from_block.parse::<u64>()?;
+  let mut page_vars: QueryVariables = query_vars.clone();
+  let mut trades: Vec<TradeInfo> = vec![];
+  loop {
+    let page_data: BitQueryData = get_page(page_vars.clone()).await?;
+    let Some(page) = page_data.data.get("EVM") else { return Err("No EVM data was found".into()) };
+    let page_len: usize = page.dex_trade_by_tokens.len();
+    let cursor: u64 = page_vars.till_block.parse::<u64>()?;
+    let min_block: Option<u64> = page.dex_trade_by_tokens.iter()
+      .filter_map(|t| t.block.number.parse::<u64>().ok())
+      .min();
+    trades.extend(page.dex_trade_by_tokens.iter().cloned());
+    println!("fetched {} trades (total {})", page_len, trades.len());
+
+    // A short page means there is nothing older left to request
+    let Some(min_block) = min_block else { break };
+    if page_len < page_vars.limit as usize || min_block <= from_block { break }
+
+    // Step the cursor back to the oldest block seen (re-requesting it as trades in that block may be split over pages)
+    // If the whole page sat within one block the cursor cannot move, so page through that block by offset instead
+    if min_block == cursor {
+      page_vars.offset += page_vars.limit;
+    } else {
+      page_vars.till_block = min_block.to_string();
+      page_vars.offset = 0;
+    }
+  }
+  Ok(order_trades(trades))
+}
+
+/// Order Trades
+/// Takes trades in BitQuery's descending order, removes trades repeated across overlapping pages
+/// and returns them sorted by block, transaction index and log index (oldest first)
+pub fn order_trades(trades: Vec<TradeInfo>) -> Vec<TradeInfo> {
+  let mut seen: HashSet<String> = HashSet::new();
+  let mut ordered: Vec<TradeInfo> = trades.into_iter().rev()
+    .filter(|t| seen.insert(t.trade_key()))
+    .collect();
+  ordered.sort_by_key(|t| (t.block.number.parse::<u64>().unwrap_or(0), t.transaction.index, t.log.as_ref().map_or(0, |log| log.index)));
+  ordered
+}
+
 #[cfg(test)]
 mod test {
   use super::*;
+  use crate::models::bitquery::LogInfo;
   use crate::{NETWORK, TOKEN, POOL};
 
   #[tokio::test]
@@ -143,7 +279,116 @@ mod test {
     let network: &str = NETWORK;
     let limit: i32 = 2;
     let offset: i32 = 0;
-    let _: () = DataManager::new(network, limit, offset, TOKEN, POOL).load_or_get_new_trade_data().await
+    // A few blocks from the pool's launch keep the walk to a handful of requests
+    let mut dm: DataManager = DataManager::new(network, limit, offset, TOKEN, POOL);
+    dm.set_block_range(18729484, 18729486);
+    let _: () = dm.load_or_get_new_trade_data().await
       .expect("Failed to get or load data");
   }
+
+  #[tokio::test]
+  async fn it_pages_back_by_block_cursor_and_offset() {
+    let mut query_vars: QueryVariables = DataManager::new(NETWORK, 2, 0, TOKEN, POOL).query_vars;
+    query_vars.from_block = "100".to_string();
+    query_vars.till_block = "110".to_string();
+    // Descending pages keyed by the cursor and offset each request should carry
+    let pages: HashMap<(&str, i32), Vec<TradeInfo>> = HashMap::from([
+      (("110", 0), vec![trade_fixture("104", "0xe", "5"), trade_fixture("103", "0xd", "4")]),
+      (("103", 0), vec![trade_fixture("103", "0xd", "4"), trade_fixture("103", "0xc", "3")]),
+      (("103", 2), vec![trade_fixture("101", "0xb", "2"), trade_fixture("100", "0xa", "1")])
+    ]);
+    let mut requests: Vec<(String, i32)> = vec![];
+    let trades: Vec<TradeInfo> = page_trade_data(&query_vars, |page_vars| {
+      requests.push((page_vars.till_block.clone(), page_vars.offset));
+      let page: Vec<TradeInfo> = pages.get(&(page_vars.till_block.as_str(), page_vars.offset)).cloned().unwrap_or_default();
+      async move { Ok(merge_trade_data(BitQueryData::new(), page)) }
+    }).await.expect("Failed to page trades");
+
+    // The page stuck on block 103 moves by offset, and reaching from_block stops the walk
+    assert_eq!(requests, vec![("110".to_string(), 0), ("103".to_string(), 0), ("103".to_string(), 2)]);
+    let blocks: Vec<&str> = trades.iter().map(|t| t.block.number.as_str()).collect();
+    assert_eq!(blocks, vec!["100", "101", "103", "103", "104"]);
+  }
+
+  #[tokio::test]
+  async fn it_stops_paging_on_a_short_page() {
+    let query_vars: QueryVariables = DataManager::new(NETWORK, 2, 0, TOKEN, POOL).query_vars;
+    let mut request_count: usize = 0;
+    let trades: Vec<TradeInfo> = page_trade_data(&query_vars, |_| {
+      request_count += 1;
+      async move { Ok(merge_trade_data(BitQueryData::new(), vec![trade_fixture("100", "0xa", "1")])) }
+    }).await.expect("Failed to page trades");
+    assert_eq!((request_count, trades.len()), (1, 1));
+  }
+
+  fn trade_fixture(block: &str, hash: &str, amount: &str) -> TradeInfo {
+    let trade_json: String = format!(r#"{{
+      "Block": {{ "Number": "{}", "Time": "2023-12-06T19:54:11Z" }},
+      "ChainId": "1",
+      "Trade": {{
+        "Amount": "{}", "Buyer": "0xbuyer", "Seller": "{}", "Price": 0.0,
+        "Currency": {{ "SmartContract": "{}", "Symbol": "SYNC" }},
+        "Dex": {{ "ProtocolName": "uniswap_v2" }},
+        "Side": {{ "Amount": "0.1", "Currency": {{ "SmartContract": "0xweth", "Symbol": "WETH" }} }}
+      }},
+      "Transaction": {{ "Hash": "{}", "From": "0xbuyer", "Index": "1" }},
+      "Log": {{ "Index": "0" }}
+    }}"#, block, amount, POOL, TOKEN, hash);
+    serde_json::from_str::<TradeInfo>(&trade_json).expect("Failed to parse trade fixture")
+  }
+
+  #[test]
+  fn it_orders_and_dedupes_overlapping_pages() {
+    // Two descending pages overlapping on block 101, where 0xb routes two equal swaps through the pool
+    let mut second_leg: TradeInfo = trade_fixture("101", "0xb", "4");
+    second_leg.log = Some(LogInfo { index: 1 });
+    let page_1 = vec![trade_fixture("102", "0xc", "5"), second_leg.clone(), trade_fixture("101", "0xb", "4")];
+    let page_2 = vec![second_leg, trade_fixture("101", "0xb", "4"), trade_fixture("100", "0xa", "2")];
+    let trades: Vec<TradeInfo> = order_trades(page_1.into_iter().chain(page_2).collect());
+    let blocks: Vec<&str> = trades.iter().map(|t| t.block.number.as_str()).collect();
+    let amounts: Vec<&str> = trades.iter().map(|t| t.trade.amount.as_str()).collect();
+    assert_eq!(blocks, vec!["100", "101", "101", "102"]);
+    assert_eq!(amounts, vec!["2", "4", "4", "5"]);
+  }
+
+  #[test]
+  fn it_orders_trades_within_a_block_by_index() {
+    let mut back_run: TradeInfo = trade_fixture("101", "0xc", "3");
+    back_run.transaction.index = 7;
+    let mut front_run: TradeInfo = trade_fixture("101", "0xa", "1");
+    front_run.transaction.index = 0;
+    let trades: Vec<TradeInfo> = order_trades(vec![back_run, trade_fixture("101", "0xb", "2"), front_run]);
+    let hashes: Vec<&str> = trades.iter().map(|t| t.transaction.hash.as_str()).collect();
+    assert_eq!(hashes, vec!["0xa", "0xb", "0xc"]);
+  }
+
+  #[test]
+  fn it_merges_new_trades_into_cache() {
+    let cached_data: BitQueryData = merge_trade_data(BitQueryData::new(), vec![trade_fixture("100", "0xa", "2"), trade_fixture("101", "0xb", "3")]);
+    assert_eq!(cached_data.last_block, 101);
+
+    // Refresh re-requests block 101 so its trade comes back again
+    let new_trades = vec![trade_fixture("101", "0xb", "3"), trade_fixture("102", "0xc", "4")];
+    let merged_data: BitQueryData = merge_trade_data(cached_data, new_trades);
+    assert_eq!(merged_data.last_block, 102);
+
+    let trades: Vec<TradeInfo> = order_trades(merged_data.data["EVM"].dex_trade_by_tokens.clone());
+    let hashes: Vec<&str> = trades.iter().map(|t| t.transaction.hash.as_str()).collect();
+    assert_eq!(hashes, vec!["0xa", "0xb", "0xc"]);
+  }
+
+  #[test]
+  fn it_keys_trades_without_log_indexes_by_amounts() {
+    // A cache saved before log indexes were requested keeps both swaps of a multi-swap transaction
+    let mut first_leg: TradeInfo = trade_fixture("101", "0xb", "4");
+    first_leg.log = None;
+    let mut second_leg: TradeInfo = trade_fixture("101", "0xb", "6");
+    second_leg.log = None;
+    let cached_data: BitQueryData = merge_trade_data(BitQueryData::new(), vec![first_leg, second_leg]);
+    assert!(!cached_data.has_log_indexes());
+    assert_eq!(cached_data.data["EVM"].dex_trade_by_tokens.len(), 2);
+
+    let refetched_data: BitQueryData = merge_trade_data(BitQueryData::new(), vec![trade_fixture("101", "0xb", "4")]);
+    assert!(refetched_data.has_log_indexes());
+  }
 }
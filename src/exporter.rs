@@ -0,0 +1,184 @@
+use crate::classifier::reason_codes;
+use crate::models::general::{Side, TradeTx};
+use csv::Writer;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// Transaction row using the column headings of the original prices.csv export,
+/// followed by the per-account fields added since
+/// Unrealized PnLs and Open Interest keep their original market wide meaning
+#[derive(Debug, Serialize)]
+pub struct TradeTxRecord<'a> {
+  #[serde(rename = "Block")]
+  pub block: u64,
+  #[serde(rename = "Block Time")]
+  pub block_time: &'a str,
+  #[serde(rename = "Transaction")]
+  pub transaction: &'a str,
+  #[serde(rename = "Side")]
+  pub side: String,
+  #[serde(rename = "Account")]
+  pub account: &'a str,
+  #[serde(rename = "Amount Base")]
+  pub amount_base: f64,
+  #[serde(rename = "Amount Quote")]
+  pub amount_quote: f64,
+  #[serde(rename = "Volume Buy")]
+  pub volume_buy: f64,
+  #[serde(rename = "Volume Sell")]
+  pub volume_sell: f64,
+  #[serde(rename = "Internal Realized PnLs")]
+  pub internal_realized_pnl: f64,
+  #[serde(rename = "External Realized PnLs")]
+  pub external_realized_pnl: f64,
+  #[serde(rename = "Unrealized PnLs")]
+  pub unrealized_pnl: f64,
+  #[serde(rename = "Open Interest")]
+  pub open_interest: f64,
+  #[serde(rename = "Prices")]
+  pub price: f64,
+  #[serde(rename = "Account Trades Open")]
+  pub account_trades_open: usize,
+  #[serde(rename = "Account Won")]
+  pub account_won: u64,
+  #[serde(rename = "Account Lost")]
+  pub account_lost: u64,
+  #[serde(rename = "External Cost Basis")]
+  pub external_cost_basis: String,
+  #[serde(rename = "External Cost Quote")]
+  pub external_cost_quote: f64,
+  #[serde(rename = "Gas Used")]
+  pub gas_used: f64,
+  #[serde(rename = "Gas Price Gwei")]
+  pub gas_price_gwei: f64,
+  #[serde(rename = "Fee Tier")]
+  pub fee_tier: f64,
+  #[serde(rename = "Swap Fee")]
+  pub swap_fee_quote: f64,
+  #[serde(rename = "Gas Fee")]
+  pub gas_fee_quote: f64,
+  #[serde(rename = "Gross Realized PnLs")]
+  pub realized_pnl_gross: f64,
+  #[serde(rename = "Net Realized PnLs")]
+  pub realized_pnl_net: f64,
+  #[serde(rename = "Gross Unrealized PnLs")]
+  pub unrealized_pnl_gross: f64,
+  #[serde(rename = "Net Unrealized PnLs")]
+  pub unrealized_pnl_net: f64,
+  #[serde(rename = "Market Trades Open")]
+  pub market_trades_open: usize,
+  #[serde(rename = "Account Unrealized PnL")]
+  pub account_unrealized_pnl: f64,
+  #[serde(rename = "Account Open Interest")]
+  pub account_open_interest: f64,
+  #[serde(rename = "Account Cumulative Realized PnL")]
+  pub account_cumulative_realized_pnl: f64,
+  #[serde(rename = "Account Cumulative External PnL")]
+  pub account_cumulative_external_pnl: f64,
+  #[serde(rename = "Trade Account")]
+  pub trade_account: &'a str,
+  #[serde(rename = "Bot Reasons")]
+  pub bot_reasons: String,
+  #[serde(rename = "Transaction Index")]
+  pub tx_index: u64,
+  #[serde(rename = "Wash Reason")]
+  pub wash_reason: &'static str,
+  #[serde(rename = "Account Wash Score")]
+  pub account_wash_score: f64,
+  #[serde(rename = "Pre-Trade Mid Price")]
+  pub pre_trade_mid_quote: f64,
+  #[serde(rename = "Slippage")]
+  pub slippage: f64,
+}
+
+impl<'a> From<&'a TradeTx> for TradeTxRecord<'a> {
+  fn from(trade_tx: &'a TradeTx) -> Self {
+    Self {
+      block: trade_tx.block_num,
+      block_time: &trade_tx.block_time,
+      transaction: &trade_tx.tx_hash,
+      side: trade_tx.side.to_string(),
+      account: &trade_tx.account_addr,
+      amount_base: trade_tx.volume_base,
+      amount_quote: trade_tx.volume_quote,
+      volume_buy: if trade_tx.side == Side::Buy { trade_tx.volume_quote } else { 0.0 },
+      volume_sell: if trade_tx.side == Side::Sell { trade_tx.volume_quote } else { 0.0 },
+      internal_realized_pnl: trade_tx.account_realized_pnl,
+      external_realized_pnl: trade_tx.account_external_pnl,
+      unrealized_pnl: trade_tx.market_unrealized_pnl,
+      open_interest: trade_tx.market_open_interest_base,
+      price: trade_tx.price_quote,
+      account_trades_open: trade_tx.account_trades_open,
+      account_won: trade_tx.account_won,
+      account_lost: trade_tx.account_lost,
+      external_cost_basis: trade_tx.external_cost_basis.map(|method| method.to_string()).unwrap_or_default(),
+      external_cost_quote: trade_tx.external_cost_quote,
+      gas_used: trade_tx.gas_used,
+      gas_price_gwei: trade_tx.gas_price_gwei,
+      fee_tier: trade_tx.fee_tier,
+      swap_fee_quote: trade_tx.swap_fee_quote,
+      gas_fee_quote: trade_tx.gas_fee_quote,
+      realized_pnl_gross: trade_tx.account_realized_pnl_gross,
+      realized_pnl_net: trade_tx.account_realized_pnl_net,
+      unrealized_pnl_gross: trade_tx.market_unrealized_pnl_gross,
+      unrealized_pnl_net: trade_tx.market_unrealized_pnl_net,
+      market_trades_open: trade_tx.market_trades_open,
+      account_unrealized_pnl: trade_tx.account_unrealized_pnl,
+      account_open_interest: trade_tx.account_open_interest_base,
+      account_cumulative_realized_pnl: trade_tx.account_cumulative_realized_pnl,
+      account_cumulative_external_pnl: trade_tx.account_cumulative_external_pnl,
+      trade_account: &trade_tx.trade_addr,
+      bot_reasons: reason_codes(&trade_tx.bot_reasons),
+      tx_index: trade_tx.tx_index,
+      wash_reason: trade_tx.wash_reason.map(|reason| reason.code()).unwrap_or(""),
+      account_wash_score: trade_tx.account_wash_score,
+      pre_trade_mid_quote: trade_tx.pre_trade_mid_quote,
+      slippage: trade_tx.slippage
+    }
+  }
+}
+
+/// Save Json
+/// Serializes any analysis output to a JSON file
+pub fn save_json<T: Serialize + ?Sized>(file_path: &str, data: &T) -> Result<(), Box<dyn Error>> {
+  let data_str: String = serde_json::to_string(data)?;
+  let mut file = File::create(file_path)?;
+  file.write_all(data_str.as_bytes())?;
+  Ok(())
+}
+
+/// Save Transactions Csv
+/// Writes the transaction ledger with one row per trade
+pub fn save_transactions_csv(file_path: &str, transactions: &[TradeTx]) -> Result<(), Box<dyn Error>> {
+  let file = File::create(file_path)?;
+  let mut wtr = Writer::from_writer(file);
+  for trade_tx in transactions {
+    wtr.serialize(TradeTxRecord::from(trade_tx))?;
+  }
+  wtr.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_writes_transactions_csv() {
+    let mut trade_tx: TradeTx = TradeTx::new("0xabc".to_string(), 18729485, 1_701_892_463, Side::Sell, "0xa".to_string());
+    trade_tx.volume_base = 403750.0;
+    trade_tx.volume_quote = 0.0625;
+    trade_tx.account_won = 1;
+
+    // One file per test process so concurrent runs never share it, removed before asserting
+    let file_path: String = std::env::temp_dir().join(format!("degentest_transactions_test_{}.csv", std::process::id())).to_string_lossy().to_string();
+    save_transactions_csv(&file_path, &[trade_tx]).expect("Failed to save csv");
+    let csv_text: String = std::fs::read_to_string(&file_path).expect("Failed to read csv");
+    std::fs::remove_file(&file_path).expect("Failed to remove csv");
+    let mut lines = csv_text.lines();
+    assert_eq!(lines.next(), Some("Block,Block Time,Transaction,Side,Account,Amount Base,Amount Quote,Volume Buy,Volume Sell,Internal Realized PnLs,External Realized PnLs,Unrealized PnLs,Open Interest,Prices,Account Trades Open,Account Won,Account Lost,External Cost Basis,External Cost Quote,Gas Used,Gas Price Gwei,Fee Tier,Swap Fee,Gas Fee,Gross Realized PnLs,Net Realized PnLs,Gross Unrealized PnLs,Net Unrealized PnLs,Market Trades Open,Account Unrealized PnL,Account Open Interest,Account Cumulative Realized PnL,Account Cumulative External PnL,Trade Account,Bot Reasons,Transaction Index,Wash Reason,Account Wash Score,Pre-Trade Mid Price,Slippage"));
+    assert_eq!(lines.next(), Some("18729485,2023-12-06T19:54:23Z,0xabc,Sell,0xa,403750.0,0.0625,0.0,0.0625,0.0,0.0,0.0,0.0,0.0,0,1,0,,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0,0.0,0.0,0.0,0.0,,,0,,0.0,0.0,0.0"));
+  }
+}
@@ -0,0 +1,124 @@
+use crate::models::general::{HolderDistribution, TradeTx};
+use std::collections::{HashMap, HashSet};
+
+/// Holder Tracker
+/// Keeps every address's open pool-acquired balance so the distribution of holdings can be
+/// snapshotted at any block, counting holders gained and lost since the previous snapshot
+/// Balances at or below min_balance_base are dust and do not count as holdings
+#[derive(Debug, Default, Clone)]
+pub struct HolderTracker {
+  pub min_balance_base: f64,
+  balances: HashMap<String, f64>,
+  previous_holders: HashSet<String>,
+  last_block_num: u64,
+  last_block_time: String,
+}
+
+impl HolderTracker {
+  pub fn new(min_balance_base: f64) -> Self {
+    Self { min_balance_base, balances: HashMap::new(), previous_holders: HashSet::new(), last_block_num: 0, last_block_time: "".to_string() }
+  }
+
+  /// Update
+  /// Sets the trading address's open balance after the trade
+  pub fn update(&mut self, trade_tx: &TradeTx) {
+    if trade_tx.account_open_interest_base > self.min_balance_base {
+      self.balances.insert(trade_tx.account_addr.clone(), trade_tx.account_open_interest_base);
+    } else {
+      self.balances.remove(&trade_tx.account_addr);
+    }
+    self.last_block_num = trade_tx.block_num;
+    self.last_block_time = trade_tx.block_time.clone();
+  }
+
+  /// Snapshot
+  /// Returns the distribution as at the last trade and makes it the baseline for new and exited holders
+  pub fn snapshot(&mut self) -> HolderDistribution {
+    let distribution: HolderDistribution = self.distribution();
+    self.previous_holders = self.balances.keys().cloned().collect();
+    distribution
+  }
+
+  /// Distribution
+  /// Returns the distribution as at the last trade, with new and exited holders counted since the last snapshot
+  pub fn distribution(&self) -> HolderDistribution {
+    let mut balances: Vec<f64> = self.balances.values().copied().collect();
+    balances.sort_by(|a, b| b.total_cmp(a));
+    let total_base: f64 = balances.iter().sum();
+    let top_share = |count: usize| if total_base > 0.0 { balances.iter().take(count).sum::<f64>() / total_base } else { 0.0 };
+
+    let holders: HashSet<&String> = self.balances.keys().collect();
+    HolderDistribution {
+      block_num: self.last_block_num,
+      datetime: self.last_block_time.clone(),
+      holder_count: balances.len() as u64,
+      total_open_base: total_base,
+      top_10_share: top_share(10),
+      top_50_share: top_share(50),
+      gini: gini(&balances),
+      hhi: herfindahl(&balances),
+      new_holders: holders.iter().filter(|holder| !self.previous_holders.contains(**holder)).count() as u64,
+      exited_holders: self.previous_holders.iter().filter(|holder| !holders.contains(holder)).count() as u64
+    }
+  }
+}
+
+/// Gini
+/// Gini coefficient of the balances, 0 when equally held and approaching 1 when one address holds everything
+pub fn gini(balances: &[f64]) -> f64 {
+  let total: f64 = balances.iter().sum();
+  if balances.is_empty() || total <= 0.0 { return 0.0; }
+  let mut ascending: Vec<f64> = balances.to_vec();
+  ascending.sort_by(|a, b| a.total_cmp(b));
+  let count: f64 = ascending.len() as f64;
+  let weighted_sum: f64 = ascending.iter().enumerate().map(|(idx, balance)| (idx + 1) as f64 * balance).sum();
+  2.0 * weighted_sum / (count * total) - (count + 1.0) / count
+}
+
+/// Herfindahl
+/// Sum of squared holding shares, from 1 / holders when equally held to 1 for a single holder
+pub fn herfindahl(balances: &[f64]) -> f64 {
+  let total: f64 = balances.iter().sum();
+  if total <= 0.0 { return 0.0; }
+  balances.iter().map(|balance| (balance / total).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::models::general::Side;
+
+  fn trade_tx(block_num: u64, account: &str, open_interest_base: f64) -> TradeTx {
+    let mut trade_tx: TradeTx = TradeTx::test_trade(block_num, Side::Buy, account, 0.0, 0.0);
+    trade_tx.account_open_interest_base = open_interest_base;
+    trade_tx
+  }
+
+  #[test]
+  fn it_measures_concentration() {
+    assert_eq!(gini(&[5.0, 5.0, 5.0, 5.0]), 0.0);
+    assert_eq!(gini(&[0.0, 0.0, 0.0, 8.0]), 0.75);
+    assert_eq!(herfindahl(&[5.0, 5.0, 5.0, 5.0]), 0.25);
+    assert_eq!(herfindahl(&[8.0]), 1.0);
+  }
+
+  #[test]
+  fn it_counts_new_and_exited_holders() {
+    let mut tracker: HolderTracker = HolderTracker::new(0.001);
+    tracker.update(&trade_tx(1, "0xa", 30.0));
+    tracker.update(&trade_tx(1, "0xb", 10.0));
+    let first: HolderDistribution = tracker.snapshot();
+    assert_eq!((first.holder_count, first.new_holders, first.exited_holders), (2, 2, 0));
+    assert_eq!(first.top_10_share, 1.0);
+    assert_eq!(first.hhi, 0.625);
+
+    // 0xa sells down to dust and 0xc buys in
+    tracker.update(&trade_tx(2, "0xa", 0.0001));
+    tracker.update(&trade_tx(3, "0xc", 10.0));
+    assert_eq!(tracker.distribution().new_holders, 1);
+    let second: HolderDistribution = tracker.snapshot();
+    assert_eq!((second.block_num, second.datetime.as_str()), (3, "2023-12-06T19:03:00Z"));
+    assert_eq!((second.holder_count, second.new_holders, second.exited_holders), (2, 1, 1));
+    assert_eq!((second.total_open_base, second.gini), (20.0, 0.0));
+  }
+}
@@ -0,0 +1,23 @@
+pub mod bars;
+pub mod classifier;
+pub mod cluster;
+pub mod costbasis;
+pub mod datamanager;
+pub mod exporter;
+pub mod holders;
+pub mod liquidity;
+pub mod mev;
+pub mod models;
+pub mod pricefeed;
+pub mod processor;
+pub mod report;
+pub mod rpcalls;
+pub mod streamer;
+pub mod swaplogs;
+pub mod wash;
+
+pub const WORKING_DIR: &str = "/Users/shaun/Code/DEVELOPMENT/degentest";
+pub const TOKEN: &str = "0xa41d2f8ee4f47d3b860a149765a7df8c3287b7f0";
+pub const POOL: &str = "0x197d7010147df7b99e9025c724f13723b29313f8";
+pub const NETWORK: &str = "eth";
+pub const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
@@ -0,0 +1,236 @@
+use crate::models::general::{LiquidityEvent, LiquidityEventKind, ReservePoint, RugAlert, Side, TradeTx};
+use std::collections::VecDeque;
+
+/// Reserve Tracker
+/// Tracks pool reserves from liquidity events and the base/quote each swap moves in and out of the pool,
+/// so the range must start at the pool's first liquidity add or be seeded with the reserves before it
+/// A move that would take a reserve below zero marks the reserves as underflowed, after which
+/// reserve depth is unknown (zero) until V3 active liquidity is reported
+/// A burn raises a rug alert when the quote burned since the peak quote reserve over the last window_blocks
+/// reaches drop_fraction of that peak, after which the peak restarts from the drained reserve
+/// Only burns count towards the drop, so a reserve drained by selling does not implicate the next small burn
+/// Depth is measured on the reserves, or on the virtual reserves of the active liquidity once a V3 swap reports it
+/// Events must arrive in block order, as they do from every data source
+#[derive(Debug, Clone)]
+pub struct ReserveTracker {
+  pub drop_fraction: f64,
+  pub window_blocks: u64,
+  pub reserve_base: f64,
+  pub reserve_quote: f64,
+  pub active_liquidity: Option<(f64, f64)>,
+  pub is_underflowed: bool,
+  series: Vec<ReservePoint>,
+  window: VecDeque<(u64, f64, f64)>,
+  burned_quote: f64,
+}
+
+impl Default for ReserveTracker {
+  fn default() -> Self {
+    Self::new(1.0, 0)
+  }
+}
+
+impl ReserveTracker {
+  pub fn new(drop_fraction: f64, window_blocks: u64) -> Self {
+    Self { drop_fraction, window_blocks, reserve_base: 0.0, reserve_quote: 0.0, active_liquidity: None, is_underflowed: false, series: vec![], window: VecDeque::new(), burned_quote: 0.0 }
+  }
+
+  /// Seed
+  /// Starts the reserves from the pool's balances at the end of block_num, before the first event applied
+  pub fn seed(&mut self, block_num: u64, reserve_base: f64, reserve_quote: f64) {
+    self.reserve_base = reserve_base;
+    self.reserve_quote = reserve_quote;
+    self.window.clear();
+    self.window.push_back((block_num, reserve_quote, self.burned_quote));
+  }
+
+  /// Apply Trade
+  /// Moves the swap's amounts in and out of the reserves, buys taking base out and paying quote in
+  pub fn apply_trade(&mut self, trade_tx: &TradeTx) {
+    match trade_tx.side {
+      Side::Buy => self.move_reserves(-trade_tx.volume_base, trade_tx.volume_quote),
+      Side::Sell => self.move_reserves(trade_tx.volume_base, -trade_tx.volume_quote)
+    }
+    if trade_tx.pool_liquidity > 0.0 && trade_tx.pool_price_quote > 0.0 {
+      self.active_liquidity = Some((trade_tx.pool_liquidity, trade_tx.pool_price_quote));
+    }
+    self.record(trade_tx.block_num, &trade_tx.block_time);
+  }
+
+  /// Apply Liquidity
+  /// Adds or removes the event's amounts and returns a rug alert if a burn drained the quote reserve
+  pub fn apply_liquidity(&mut self, liquidity_event: &LiquidityEvent) -> Option<RugAlert> {
+    match liquidity_event.kind {
+      LiquidityEventKind::Mint => self.move_reserves(liquidity_event.amount_base, liquidity_event.amount_quote),
+      LiquidityEventKind::Burn => {
+        self.move_reserves(-liquidity_event.amount_base, -liquidity_event.amount_quote);
+        self.burned_quote += liquidity_event.amount_quote;
+      }
+    }
+    self.record(liquidity_event.block_num, &liquidity_event.block_time);
+    if liquidity_event.kind == LiquidityEventKind::Mint { return None; }
+
+    let (peak_block_num, peak_reserve_quote, peak_burned_quote) = self.window.iter()
+      .copied()
+      .max_by(|(_, a, _), (_, b, _)| a.total_cmp(b))?;
+    if peak_reserve_quote <= 0.0 { return None; }
+    let drop_fraction: f64 = (self.burned_quote - peak_burned_quote) / peak_reserve_quote;
+    if drop_fraction < self.drop_fraction { return None; }
+
+    self.window.clear();
+    self.window.push_back((liquidity_event.block_num, self.reserve_quote, self.burned_quote));
+    Some(RugAlert {
+      block_num: liquidity_event.block_num,
+      datetime: liquidity_event.block_time.clone(),
+      tx_hash: liquidity_event.tx_hash.clone(),
+      provider_addr: liquidity_event.provider_addr.clone(),
+      peak_block_num,
+      peak_reserve_quote,
+      reserve_quote: self.reserve_quote,
+      drop_fraction
+    })
+  }
+
+  /// Series
+  /// Returns the reserves at the end of each block that had a swap or liquidity event
+  pub fn series(&self) -> &[ReservePoint] {
+    &self.series
+  }
+
+  /// Current
+  /// Returns the reserves after the last event applied, if any
+  pub fn current(&self) -> Option<&ReservePoint> {
+    self.series.last()
+  }
+
+  /// Depth Reserves
+  /// Returns the base and quote reserves trades execute against, the virtual reserves
+  /// L / sqrt(P) and L * sqrt(P) for V3 pools and the actual reserves otherwise
+  pub fn depth_reserves(&self) -> (f64, f64) {
+    match self.active_liquidity {
+      Some((liquidity, price_quote)) => (liquidity / price_quote.sqrt(), liquidity * price_quote.sqrt()),
+      None if self.is_underflowed => (0.0, 0.0),
+      None => (self.reserve_base, self.reserve_quote)
+    }
+  }
+
+  /// Mid Price
+  /// Returns the pool's current price in quote per base, None while the pool is empty
+  pub fn mid_price(&self) -> Option<f64> {
+    let (depth_base, depth_quote) = self.depth_reserves();
+    if depth_base > 0.0 && depth_quote > 0.0 { Some(depth_quote / depth_base) } else { None }
+  }
+
+  fn move_reserves(&mut self, base: f64, quote: f64) {
+    // Allow for float rounding before treating a negative reserve as missing liquidity
+    let (reserve_base, reserve_quote) = (self.reserve_base + base, self.reserve_quote + quote);
+    if !self.is_underflowed && (reserve_base < -1e-9 || reserve_quote < -1e-9) {
+      println!("reserves went negative, the range must start at the pool's first liquidity add or be seeded");
+      self.is_underflowed = true;
+    }
+    self.reserve_base = reserve_base.max(0.0);
+    self.reserve_quote = reserve_quote.max(0.0);
+  }
+
+  fn record(&mut self, block_num: u64, block_time: &str) {
+    while self.window.front().is_some_and(|(window_block, _, _)| window_block + self.window_blocks < block_num) {
+      self.window.pop_front();
+    }
+    self.window.push_back((block_num, self.reserve_quote, self.burned_quote));
+
+    // A 1 quote buy lifts the constant product mid price by ((y + 1) / y)^2
+    let (depth_base, depth_quote) = self.depth_reserves();
+    let point: ReservePoint = ReservePoint {
+      block_num,
+      datetime: block_time.to_string(),
+      reserve_base: self.reserve_base,
+      reserve_quote: self.reserve_quote,
+      price_quote: self.mid_price().unwrap_or(0.0),
+      k: depth_base * depth_quote,
+      liquidity: (depth_base * depth_quote).sqrt(),
+      price_impact_per_quote: if depth_quote > 0.0 { ((depth_quote + 1.0) / depth_quote).powi(2) - 1.0 } else { 0.0 }
+    };
+    match self.series.last_mut() {
+      Some(last) if last.block_num == block_num => *last = point,
+      _ => self.series.push(point)
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn liquidity_event(block_num: u64, kind: LiquidityEventKind, amount_base: f64, amount_quote: f64) -> LiquidityEvent {
+    LiquidityEvent {
+      block_num,
+      block_time: "".to_string(),
+      tx_hash: format!("0x{}", block_num),
+      tx_index: 0,
+      log_index: 0,
+      kind,
+      provider_addr: "0xdeployer".to_string(),
+      amount_base,
+      amount_quote
+    }
+  }
+
+  #[test]
+  fn it_tracks_reserves_and_alerts_on_rugs() {
+    let mut tracker: ReserveTracker = ReserveTracker::new(0.5, 10);
+    assert!(tracker.apply_liquidity(&liquidity_event(100, LiquidityEventKind::Mint, 1000.0, 10.0)).is_none());
+    tracker.apply_trade(&TradeTx::test_trade(101, Side::Buy, "0xa", 100.0, 0.02));
+    assert_eq!((tracker.reserve_base, tracker.reserve_quote), (900.0, 12.0));
+
+    // A small removal is not a rug, but two within the window add up to one
+    assert!(tracker.apply_liquidity(&liquidity_event(102, LiquidityEventKind::Burn, 300.0, 4.0)).is_none());
+    let alert: RugAlert = tracker.apply_liquidity(&liquidity_event(105, LiquidityEventKind::Burn, 300.0, 4.0)).expect("Expected rug alert");
+    assert_eq!((alert.peak_block_num, alert.peak_reserve_quote, alert.reserve_quote), (101, 12.0, 4.0));
+    assert!((alert.drop_fraction - 2.0 / 3.0).abs() < 1e-9);
+    assert!(tracker.apply_liquidity(&liquidity_event(106, LiquidityEventKind::Burn, 100.0, 1.0)).is_none());
+
+    let blocks: Vec<u64> = tracker.series().iter().map(|point| point.block_num).collect();
+    assert_eq!(blocks, vec![100, 101, 102, 105, 106]);
+    assert_eq!(tracker.series()[0].price_quote, 0.01);
+    // 1 quote into 10 quote of depth moves the mid by 21%
+    assert_eq!((tracker.series()[0].k, tracker.series()[0].liquidity), (10000.0, 100.0));
+    assert!((tracker.series()[0].price_impact_per_quote - 0.21).abs() < 1e-9);
+  }
+
+  #[test]
+  fn it_ignores_small_burns_after_sells_drain_the_pool() {
+    let mut tracker: ReserveTracker = ReserveTracker::new(0.5, 10);
+    tracker.apply_liquidity(&liquidity_event(100, LiquidityEventKind::Mint, 1000.0, 10.0));
+    tracker.apply_trade(&TradeTx::test_trade(101, Side::Sell, "0xa", 9000.0, 0.001));
+    assert_eq!(tracker.reserve_quote, 1.0);
+    assert!(tracker.apply_liquidity(&liquidity_event(102, LiquidityEventKind::Burn, 100.0, 0.1)).is_none());
+  }
+
+  #[test]
+  fn it_flags_unseeded_reserves_and_seeds_them() {
+    let sell: TradeTx = TradeTx::test_trade(101, Side::Sell, "0xa", 100.0, 0.01);
+
+    let mut unseeded: ReserveTracker = ReserveTracker::default();
+    unseeded.apply_trade(&sell);
+    assert!(unseeded.is_underflowed);
+    assert_eq!(unseeded.mid_price(), None);
+
+    let mut seeded: ReserveTracker = ReserveTracker::default();
+    seeded.seed(100, 1000.0, 10.0);
+    seeded.apply_trade(&sell);
+    assert!(!seeded.is_underflowed);
+    assert_eq!((seeded.reserve_base, seeded.reserve_quote), (1100.0, 9.0));
+  }
+
+  #[test]
+  fn it_measures_depth_on_v3_active_liquidity() {
+    let mut tracker: ReserveTracker = ReserveTracker::default();
+    let mut buy: TradeTx = TradeTx::test_trade(101, Side::Buy, "0xa", 0.0, 0.0);
+    buy.pool_liquidity = 20.0;
+    buy.pool_price_quote = 4.0;
+    tracker.apply_trade(&buy);
+    assert_eq!(tracker.depth_reserves(), (10.0, 40.0));
+    assert_eq!(tracker.mid_price(), Some(4.0));
+    assert_eq!(tracker.current().map(|point| point.liquidity), Some(20.0));
+  }
+}
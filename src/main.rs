@@ -1,306 +1,351 @@
-mod datamanager;
-mod models;
-
-use models::address::AddressRecords;
-use models::bitquery::Trade;
-use models::general::{Analysis, Criteria, DollarBar, PnlBar, Side, TradeTx, VolumeBar};
-use models::traits::TimeBars;
-
+use degentest::{datamanager, report, rpcalls, exporter, streamer, swaplogs, NETWORK, POOL, TOKEN, WORKING_DIR};
+use degentest::bars::{BarKind, BarSampler, BarTrigger, SampledBars};
+use degentest::bars::activity::{BaseVolumeBarBuilder, DollarBarBuilder, PnlBarBuilder, VolumeBarBuilder};
+use degentest::bars::information::{ImbalanceBarBuilder, InformationMeasure, RunBarBuilder, TickBarBuilder};
+use degentest::bars::time::{TimeBarBuilder, TimeInterval};
+use degentest::classifier::BotClassifier;
+use degentest::cluster::{self, WalletClusterer};
+use degentest::costbasis::ExternalCostBasisResolver;
+use degentest::holders::HolderTracker;
+use degentest::models::address::{CostBasisMethod, PositionClosed};
+use degentest::models::bitquery::TradeInfo;
+use degentest::liquidity::ReserveTracker;
+use degentest::models::general::{Analysis, Criteria, DataSource, ExternalCostBasis, FundingTransfer, LiquidityEvent, LiquidityEventKind, RunMode};
+use degentest::pricefeed::QuoteUsdSeries;
+use degentest::streamer::PoolEvent;
+use degentest::processor::{ProcessedTrade, TradeProcessor};
+use degentest::wash::WashDetector;
 use std::collections::HashMap;
-use std::io::Write;
 
-const WORKING_DIR: &str = "/Users/shaun/Code/DEVELOPMENT/degentest";
-const TOKEN: &str = "0xa41d2f8ee4f47d3b860a149765a7df8c3287b7f0";
-const POOL: &str = "0x197d7010147df7b99e9025c724f13723b29313f8";
-const NETWORK: &str = "eth";
 const OFFSET: i32 = 0;
 const LIMIT: i32 = 10000;
+const POOL_START_BLOCK: u64 = 18729484;
+const FROM_BLOCK: u64 = POOL_START_BLOCK;
+const TILL_BLOCK: u64 = u64::MAX;
+const DATA_SOURCE: DataSource = DataSource::BitQuery;
 const DOLLAR_BAR_LIMIT: f64 = 10.0;
-const criteria: Criteria = Criteria { is_dollar_bars: true, is_volume_bars: true, is_pnl_bars: true, is_transactions_bars: true };
+const BASE_VOLUME_BAR_LIMIT: f64 = 10.0;
+const VOLUME_BAR_TRIGGER: BarTrigger = BarTrigger::Notional(DOLLAR_BAR_LIMIT);
+const PNL_BAR_TRIGGER: BarTrigger = BarTrigger::Notional(DOLLAR_BAR_LIMIT);
+const TIME_BAR_INTERVAL: TimeInterval = TimeInterval::OneMinute;
+const TIME_BAR_FORWARD_FILL: bool = false;
+const TICK_BAR_LIMIT: u64 = 50;
+const INFORMATION_BAR_MEASURE: InformationMeasure = InformationMeasure::Dollar;
+const INFORMATION_BAR_EXPECTED_TICKS: f64 = 50.0;
+const INFORMATION_BAR_EWMA_ALPHA: f64 = 0.1;
+const COST_BASIS_METHOD: CostBasisMethod = CostBasisMethod::Fifo;
+const EXTERNAL_COST_BASIS: ExternalCostBasis = ExternalCostBasis::Zero;
+const EXTERNAL_COST_BASIS_PATH: Option<&str> = None;
+const POOL_FEE_TIER: Option<f64> = None;
+const TRADER_PROFILE_SORT_COLUMN: &str = "realized_pnl";
+const TRADER_PROFILE_SORT_DESCENDING: bool = true;
+const SNIPER_EARLY_BLOCKS: u64 = 3;
+const BOT_MULTI_TRADE_THRESHOLD: u64 = 3;
+const EXCLUDE_BOTS_FROM_BARS: bool = false;
+const WASH_WINDOW_BLOCKS: u64 = 5;
+const WASH_SIZE_TOLERANCE: f64 = 0.02;
+const WASH_MIN_ROUND_TRIPS: u64 = 3;
+const CLUSTER_TIMING_BLOCKS: u64 = 1;
+const CLUSTER_MIN_CO_TRADES: u64 = 5;
+const CLUSTER_SIZE_TOLERANCE: f64 = 0.01;
+const CLUSTER_MIN_MATCHING_SIZES: u64 = 3;
+const FUNDING_TRANSFERS_PATH: Option<&str> = None;
+const FILL_FUNDING_FROM_RPC: bool = false;
+const FETCH_LIQUIDITY_EVENTS: bool = false;
+const RUG_DROP_FRACTION: f64 = 0.5;
+const RUG_WINDOW_BLOCKS: u64 = 10;
+const HOLDER_MIN_BALANCE_BASE: f64 = 0.000000001;
+const HOLDER_SNAPSHOT_BAR: BarKind = BarKind::Time;
+const QUOTE_USD_PRICES_PATH: Option<&str> = None;
+const CRITERIA: Criteria = Criteria { is_dollar_bars: true, is_base_volume_bars: true, is_volume_bars: true, is_pnl_bars: true, is_time_bars: true,
+    is_tick_bars: true, is_imbalance_bars: true, is_run_bars: true, is_trader_profiles: true,
+    is_mev_attacks: true, is_wallet_clusters: true, is_liquidity: true,
+    is_holder_distributions: true, is_transactions_bars: true };
+const RUN_MODE: RunMode = RunMode::Batch;
+
+/// Rpc Url
+/// Reads the JSON-RPC endpoint from the RPC_URL environment variable so provider keys stay out of the source
+fn rpc_url() -> String {
+    std::env::var("RPC_URL").expect("RPC_URL must be set to an Ethereum JSON-RPC endpoint, e.g. https://eth-mainnet.g.alchemy.com/v2/<key>")
+}
+
+/// Ws Url
+/// Reads the WebSocket endpoint for live streaming from the WS_URL environment variable
+fn ws_url() -> String {
+    std::env::var("WS_URL").expect("WS_URL must be set to an Ethereum WebSocket endpoint, e.g. wss://eth-mainnet.g.alchemy.com/v2/<key>")
+}
 
 #[tokio::main]
 async fn main() {
 
-    // Load or Fetch Data
-    let mut dm = datamanager::DataManager::new(NETWORK, LIMIT, OFFSET, TOKEN, POOL);
-    let extract_res = dm.load_or_get_new_trade_data().await;
-    let _: () = match extract_res {
-        Ok(dm) => dm,
-        Err(e) => panic!("{}", e)
+    // Initialize bar sampler, each bar type closing on its own trigger
+    let mut bar_sampler: BarSampler = BarSampler::new();
+    if CRITERIA.is_dollar_bars { bar_sampler.dollar = Some(DollarBarBuilder::new(DOLLAR_BAR_LIMIT)); }
+    if CRITERIA.is_base_volume_bars { bar_sampler.base_volume = Some(BaseVolumeBarBuilder::new(BASE_VOLUME_BAR_LIMIT)); }
+    if CRITERIA.is_volume_bars { bar_sampler.volume = Some(VolumeBarBuilder::new(VOLUME_BAR_TRIGGER)); }
+    if CRITERIA.is_pnl_bars { bar_sampler.pnl = Some(PnlBarBuilder::new(PNL_BAR_TRIGGER)); }
+    if CRITERIA.is_tick_bars { bar_sampler.tick = Some(TickBarBuilder::new(TICK_BAR_LIMIT)); }
+    if CRITERIA.is_imbalance_bars {
+        bar_sampler.imbalance = Some(ImbalanceBarBuilder::new(INFORMATION_BAR_MEASURE, INFORMATION_BAR_EXPECTED_TICKS, INFORMATION_BAR_EWMA_ALPHA));
+    }
+    if CRITERIA.is_run_bars {
+        bar_sampler.run = Some(RunBarBuilder::new(INFORMATION_BAR_MEASURE, INFORMATION_BAR_EXPECTED_TICKS, INFORMATION_BAR_EWMA_ALPHA));
+    }
+    if CRITERIA.is_time_bars { bar_sampler.time = Some(TimeBarBuilder::new(TIME_BAR_INTERVAL, TIME_BAR_FORWARD_FILL)); }
+
+    // Initialize trade processor
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, CRITERIA, bar_sampler);
+    processor.set_cost_basis_method(COST_BASIS_METHOD);
+    let external_cost_basis: ExternalCostBasisResolver = match (EXTERNAL_COST_BASIS, EXTERNAL_COST_BASIS_PATH) {
+        (ExternalCostBasis::Imported, Some(file_path)) if file_path.ends_with(".json") => {
+            ExternalCostBasisResolver::from_json(file_path).expect("Failed to load external cost basis json")
+        },
+        (ExternalCostBasis::Imported, Some(file_path)) => {
+            ExternalCostBasisResolver::from_csv(file_path).expect("Failed to load external cost basis csv")
+        },
+        (ExternalCostBasis::Imported, None) => panic!("Imported external cost basis requires EXTERNAL_COST_BASIS_PATH"),
+        (method, _) => ExternalCostBasisResolver::new(method)
     };
-    let Some(trades_data) = dm.dex_trade_data else { panic!("No data was found") };
-
-    // // Initialize variables
-    // let mut blocks: Vec<String> = vec![];
-    // let mut block_times: Vec<String> = vec![];
-    // let mut transactions: Vec<String> = vec![];
-    // let mut accounts: Vec<String> = vec![];
-    // let mut amounts_base: Vec<f64> = vec![];
-    // let mut amounts_quote: Vec<f64> = vec![];
-    // let mut sides: Vec<String> = vec![];
-    // let mut prices: Vec<f64> = vec![];
-
-    // let mut internal_realized_pnls: Vec<f64> = vec![];
-    // let mut external_realized_pnls: Vec<f64> = vec![];
-    // let mut unrealized_pnls: Vec<f64> = vec![];
-    // let mut open_interests: Vec<f64> = vec![];
-    // let mut volumes_quote_buy: Vec<f64> = vec![];
-    // let mut volumes_quote_sell: Vec<f64> = vec![];
-    // let mut unique_address_trades: Vec<u64> = vec![];
-
-    let mut unique_address_trade_counts_hm: HashMap<String, u64> = HashMap::new();
-    let mut address_records_hm: HashMap<String, AddressRecords> = HashMap::new();
-
-    // Initialize for Dollar Bars
-    let mut analysis: Analysis = Analysis::new();
-    let mut dollar_bars: Vec<DollarBar> = vec![];
-    let mut volume_bars: Vec<VolumeBar> = vec![];
-    let mut pnl_bars: Vec<PnlBar> = vec![];
-    let mut trade_transactions: Vec<TradeTx> = vec![];
-
-    let mut is_init: bool = true;
-    let mut cumulative_qty: f64 = 0.0;
-    let mut dollar_bar: DollarBar = DollarBar::new();
-    let mut volume_bar: VolumeBar = VolumeBar::new();
-    let mut pnl_bar: PnlBar = PnlBar::new();
+    processor.set_external_cost_basis(external_cost_basis);
+    if let Some(fee_tier) = POOL_FEE_TIER { processor.set_fee_tier(fee_tier); }
+    // Liquidity was added in the pool's start block, where the first trades land
+    let mut bot_classifier: BotClassifier = BotClassifier::new(SNIPER_EARLY_BLOCKS, BOT_MULTI_TRADE_THRESHOLD);
+    bot_classifier.launch_block = Some(POOL_START_BLOCK);
+    bot_classifier.liquidity_add_block = Some(POOL_START_BLOCK);
+    processor.set_bot_classifier(bot_classifier, EXCLUDE_BOTS_FROM_BARS);
+    let mut wash_detector: WashDetector = WashDetector::new(WASH_WINDOW_BLOCKS, WASH_SIZE_TOLERANCE, WASH_MIN_ROUND_TRIPS);
+    let mut wallet_clusterer: WalletClusterer = WalletClusterer::new(CLUSTER_TIMING_BLOCKS, CLUSTER_MIN_CO_TRADES, CLUSTER_SIZE_TOLERANCE, CLUSTER_MIN_MATCHING_SIZES);
+    if let Some(file_path) = FUNDING_TRANSFERS_PATH {
+        let funding_transfers: Vec<FundingTransfer> = if file_path.ends_with(".json") {
+            cluster::load_funding_transfers_json(file_path).expect("Failed to load funding transfers json")
+        } else {
+            cluster::load_funding_transfers_csv(file_path).expect("Failed to load funding transfers csv")
+        };
+        wash_detector.add_funding_transfers(&funding_transfers);
+        wallet_clusterer.add_funding_transfers(&funding_transfers);
+    }
+    processor.set_wash_detector(wash_detector);
+    processor.set_wallet_clusterer(wallet_clusterer);
+    processor.set_reserve_tracker(ReserveTracker::new(RUG_DROP_FRACTION, RUG_WINDOW_BLOCKS));
+    processor.set_holder_tracker(HolderTracker::new(HOLDER_MIN_BALANCE_BASE), HOLDER_SNAPSHOT_BAR);
+    if let Some(file_path) = QUOTE_USD_PRICES_PATH {
+        let quote_usd_series: QuoteUsdSeries = QuoteUsdSeries::from_csv(file_path).expect("Failed to load quote usd prices");
+        processor.set_quote_usd_series(quote_usd_series);
+    }
 
-    // Calculate metrics for each trade
-    for trade in trades_data {
-        let block_num: u64 = trade.block.number.parse::<u64>().unwrap();
-        let side: Side = if trade.trade.buyer != POOL { Side::Buy } else { Side::Sell };
-        let mut trade_tx: TradeTx = TradeTx::new(trade.transaction.hash, block_num, trade.block.time, side, trade.transaction.from);
+    // Stream live trades and liquidity events, emitting bars and rug alerts as they happen
+    if RUN_MODE == RunMode::Stream {
+        let stream_res = streamer::stream_pool_events(&ws_url(), POOL, TOKEN, |pool_event| match pool_event {
+            PoolEvent::Reserves(block_num, reserve_base, reserve_quote) => processor.reserve_tracker.seed(block_num, reserve_base, reserve_quote),
+            PoolEvent::Liquidity(liquidity_event) => {
+                if let Some(alert) = processor.process_liquidity_event(liquidity_event) {
+                    println!("{}", serde_json::to_string(&alert).expect("Failed to serialize Rug Alert"));
+                }
+            },
+            PoolEvent::Trade(trade) => {
+                let processed: ProcessedTrade = match processor.process_trade(*trade) {
+                    Ok(processed) => processed,
+                    Err(e) => {
+                        println!("skipping trade: {}", e);
+                        return;
+                    }
+                };
+                let closed_bars: SampledBars = processed.closed_bars;
+                if let Some(bar) = closed_bars.dollar_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Dollar Bar")); }
+                if let Some(bar) = closed_bars.base_volume_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Base Volume Bar")); }
+                if let Some(bar) = closed_bars.volume_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Volume Bar")); }
+                if let Some(bar) = closed_bars.pnl_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Pnl Bar")); }
+                if let Some(bar) = closed_bars.tick_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Tick Bar")); }
+                if let Some(bar) = closed_bars.imbalance_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Imbalance Bar")); }
+                if let Some(bar) = closed_bars.run_bar { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Run Bar")); }
+                for bar in closed_bars.time_bars { println!("{}", serde_json::to_string(&bar).expect("Failed to serialize Time Bar")); }
+                for attack in processed.mev_attacks { println!("{}", serde_json::to_string(&attack).expect("Failed to serialize Mev Attack")); }
+                if let Some(distribution) = processed.holder_distribution { println!("{}", serde_json::to_string(&distribution).expect("Failed to serialize Holder Distribution")); }
+            }
+        }).await;
+        if let Err(e) = stream_res { panic!("{}", e) }
+        return;
+    }
 
-        let amount_base: f64 = trade.trade.amount.parse::<f64>().expect("Failed to convert String amount to f64");
-        let amount_quote: f64 = trade.trade.side.amount.parse::<f64>().expect("Failed to convert String amount to f64");
-        let price_quote: f64 = amount_quote / amount_base;
-        trade_tx.volume_base = amount_base;
-        trade_tx.volume_quote = amount_quote;
-        trade_tx.price_quote = price_quote;
+    // Load or Fetch Data
+    let mut trades_data: Vec<TradeInfo> = match DATA_SOURCE {
+        DataSource::BitQuery => {
+            let mut dm = datamanager::DataManager::new(NETWORK, LIMIT, OFFSET, TOKEN, POOL);
+            dm.set_block_range(FROM_BLOCK, TILL_BLOCK);
+            let extract_res = dm.load_or_get_new_trade_data().await;
+            let _: () = match extract_res {
+                Ok(dm) => dm,
+                Err(e) => panic!("{}", e)
+            };
+            let Some(trades_data) = dm.dex_trade_data else { panic!("No data was found") };
+            trades_data
+        },
+        DataSource::SwapLogs => {
+            match swaplogs::get_swap_trades(&rpc_url(), POOL, TOKEN, FROM_BLOCK, TILL_BLOCK).await {
+                Ok(trades_data) => trades_data,
+                Err(e) => panic!("{}", e)
+            }
+        }
+    };
 
-        // Calculate Volume
-        let volume_quote_buy: f64 = if trade_tx.side == Side::Buy { amount_quote } else { 0.0 };
-        let volume_quote_sell: f64 = if trade_tx.side == Side::Sell { amount_quote } else { 0.0 };
+    // Fill gas used from receipts, as BitQuery only reports each transaction's gas limit
+    if let Err(e) = rpcalls::fill_transaction_gas(&rpc_url(), &mut trades_data).await { panic!("{}", e) }
 
-        // Calculate Count of Trades for Given Address
-        let current_address_count_opt = unique_address_trade_counts_hm.get(trade_tx.account_addr.as_str());
-        let addr_trade_count: u64 = match current_address_count_opt {
-            Some(&count) => count + 1,
-            None => 1
-        };
-        unique_address_trade_counts_hm.insert(trade_tx.account_addr.clone(), addr_trade_count);
+    // Look up each trader's funding parent before its first trade
+    if FILL_FUNDING_FROM_RPC {
+        let mut first_blocks: HashMap<String, u64> = HashMap::new();
+        for trade in trades_data.iter() {
+            let block_num: u64 = trade.block.number.parse::<u64>().unwrap_or(0);
+            first_blocks.entry(trade.transaction.from.to_lowercase()).or_insert(block_num);
+        }
+        match rpcalls::get_funding_transfers(&rpc_url(), &first_blocks).await {
+            Ok(funding_transfers) => {
+                processor.wash_detector.add_funding_transfers(&funding_transfers);
+                processor.wallet_clusterer.add_funding_transfers(&funding_transfers);
+            },
+            Err(e) => panic!("{}", e)
+        }
+    }
 
-        // Update records with current trade and increment realized P&L
-        let mut account_realized_internal_pnl = 0.0;
-        let mut account_realized_external_pnl = 0.0;
-        let addr_record_opt = address_records_hm.get(trade_tx.account_addr.as_str());
-        let mut record: AddressRecords = if let Some(addr_record) = addr_record_opt { addr_record.clone() } else { AddressRecords::new() };
-        if trade_tx.side == Side::Buy {
-            record.open_position(amount_base, price_quote);
-        } else {
-            let (internal_pnl, external_pnl) = record.close_positions(amount_base, price_quote);
-            account_realized_internal_pnl = internal_pnl;
-            account_realized_external_pnl = external_pnl;
+    // Fetch pool liquidity adds and removals, which the swap data sources do not include
+    let liquidity_events: Vec<LiquidityEvent> = if FETCH_LIQUIDITY_EVENTS {
+        match swaplogs::get_liquidity_events(&rpc_url(), POOL, TOKEN, FROM_BLOCK, TILL_BLOCK).await {
+            Ok(liquidity_events) => liquidity_events,
+            Err(e) => panic!("{}", e)
         }
-        trade_tx.account_won = record.count_profit;
-        trade_tx.account_lost = record.count_loss;
-        trade_tx.account_realized_pnl = account_realized_internal_pnl;
-        trade_tx.account_external_pnl = account_realized_external_pnl;
-        address_records_hm.insert(trade_tx.account_addr.clone(), record);
+    } else {
+        vec![]
+    };
+    // The first mint is only the pool's initial liquidity add when the range starts at pool creation,
+    // otherwise it is a later top-up and the configured block is kept
+    if FROM_BLOCK <= POOL_START_BLOCK {
+        if let Some(first_mint) = liquidity_events.iter().find(|event| event.kind == LiquidityEventKind::Mint) {
+            processor.bot_classifier.liquidity_add_block = Some(first_mint.block_num);
+        }
+    }
 
-        // Update unrealized records
-        let mut account_unrealized_pnl = 0.0;
-        let mut account_open_interest_base = 0.0;
-        let mut account_trades_open = 0;
-        for (_, record_obj) in &address_records_hm {
-            account_open_interest_base += record_obj.get_open_interest();
-            account_unrealized_pnl += record_obj.calculate_unrealized_position(price_quote);
-            account_trades_open = record_obj.count_open_positions();
+    // Start the reserves from the pool's balances before the range, as mints before it are not replayed
+    if FETCH_LIQUIDITY_EVENTS && FROM_BLOCK > POOL_START_BLOCK {
+        match swaplogs::get_pool_reserves(&rpc_url(), POOL, TOKEN, FROM_BLOCK - 1).await {
+            Ok((reserve_base, reserve_quote)) => processor.reserve_tracker.seed(FROM_BLOCK - 1, reserve_base, reserve_quote),
+            Err(e) => panic!("{}", e)
         }
-        trade_tx.account_unrealized_pnl = account_unrealized_pnl;
-        trade_tx.account_open_interest_base = account_open_interest_base;
-        trade_tx.account_trades_open = account_trades_open;
+    }
 
-        // Update dollar bars
-        if criteria.is_dollar_bars || criteria.is_pnl_bars || criteria.is_volume_bars {
-            volume_bar.volume_buys += volume_quote_buy;
-            volume_bar.volume_sells += volume_quote_sell;
-    
-            pnl_bar.internal_realized_pnl = account_realized_internal_pnl;
-            pnl_bar.external_realized_pnl = account_realized_external_pnl;
-    
-            dollar_bar.close = price_quote;
-    
-            if is_init == true {
-                dollar_bar.datetime = trade_tx.block_time.clone();
-                volume_bar.datetime = trade_tx.block_time.clone();
-                pnl_bar.datetime = trade_tx.block_time.clone();
-    
-                dollar_bar.open = price_quote;
-                dollar_bar.high = price_quote;
-                dollar_bar.low = price_quote;
-    
-                is_init = false;
-            }
-    
-            if price_quote > dollar_bar.high { dollar_bar.high = price_quote; }
-            if price_quote < dollar_bar.low { dollar_bar.low = price_quote; }
-    
-            cumulative_qty += amount_base;
-    
-            if cumulative_qty >= DOLLAR_BAR_LIMIT {
-                dollar_bars.push(dollar_bar.clone());
-                volume_bars.push(volume_bar);
-                pnl_bars.push(pnl_bar);
-                dollar_bar = DollarBar::new();
-                volume_bar = VolumeBar::new();
-                pnl_bar = PnlBar::new();
-                cumulative_qty = 0.0;
-                is_init = true;
+    // Calculate metrics for each trade, applying liquidity events in order before the trades after them
+    let mut liquidity_events = liquidity_events.into_iter().peekable();
+    for trade in trades_data {
+        let trade_order: (u64, u64) = (trade.block.number.parse::<u64>().unwrap_or(0), trade.transaction.index);
+        while let Some(liquidity_event) = liquidity_events.next_if(|event| (event.block_num, event.tx_index) <= trade_order) {
+            if let Some(alert) = processor.process_liquidity_event(liquidity_event) {
+                println!("{}", serde_json::to_string(&alert).expect("Failed to serialize Rug Alert"));
             }
         }
-
-        // blocks.push(block_num);
-        // block_times.push(block_time);
-        // transactions.push(tx_hash);
-        // accounts.push(account_addr);
-        // amounts_base.push(amount_base);
-        // amounts_quote.push(amount_quote);
-        // sides.push(side.to_string());
-        // prices.push(price_quote);
-
-        // volumes_quote_buy.push(volume_quote_buy);
-        // volumes_quote_sell.push(volume_quote_sell);
-        // unique_address_trades.push(addr_trade_count);
-
-        // internal_realized_pnls.push(realized_internal_pnl);
-        // external_realized_pnls.push(realized_external_pnl);
-        // unrealized_pnls.push(unrealized_pnl);
-        // open_interests.push(open_interest_base);
+        if let Err(e) = processor.process_trade(trade) { println!("skipping trade: {}", e); }
     }
+    for liquidity_event in liquidity_events {
+        if let Some(alert) = processor.process_liquidity_event(liquidity_event) {
+            println!("{}", serde_json::to_string(&alert).expect("Failed to serialize Rug Alert"));
+        }
+    }
+    processor.finish();
 
-    // Update analysis bars
-    if criteria.is_dollar_bars { analysis.dollar_bars = Some(dollar_bars); }
-    if criteria.is_volume_bars { analysis.volume_bars = Some(volume_bars); }
-    if criteria.is_pnl_bars { analysis.pnl_bars = Some(pnl_bars); }
-    if criteria.is_transactions_bars { analysis.transactions = Some(trade_transactions); }
-
-    // let file_name = "dollarbars.txt";
-    // let db_str: String = serde_json::to_string::<Vec<DollarBar>>(&analysis.dollar_bars.unwrap()).expect("Failed to serialize Dollar Bars");
-    // let mut file = std::fs::File::create(file_name).expect("Failed to create file");
-    // file.write_all(db_str.as_bytes()).expect("Failed to write to file");
-
-    // for (i, qty) in amounts_quote.iter().enumerate() {
-    //     let current_price = prices[i];
-
-    //     volume_bar.volume_buys += volumes_quote_buy[i];
-    //     volume_bar.volume_sells += volumes_quote_sell[i];
-
-    //     pnl_bar.internal_realized_pnl = internal_realized_pnls[i];
-    //     pnl_bar.external_realized_pnl = external_realized_pnls[i];
-
-    //     dollar_bar.close = current_price;
-    //     if is_init == true {
-    //         dollar_bar.datetime = block_times[i].clone();
-    //         volume_bar.datetime = block_times[i].clone();
-    //         pnl_bar.datetime = block_times[i].clone();
-
-    //         dollar_bar.open = current_price;
-    //         dollar_bar.high = current_price;
-    //         dollar_bar.low = current_price;
-
-    //         is_init = false;
-    //     }
-
-    //     if current_price > dollar_bar.high { dollar_bar.high = current_price; }
-    //     if current_price < dollar_bar.low { dollar_bar.low = current_price; }
+    // Update analysis bars and transactions
+    let analysis: Analysis = processor.snapshot();
 
-    //     cumulative_qty += qty;
+    // Save transactions
+    if let Some(transactions) = &analysis.transactions {
+        let file_path: String = format!("{}/{}_transactions", WORKING_DIR, POOL);
+        exporter::save_json(&format!("{}.json", file_path), transactions).expect("Failed to save transactions json");
+        exporter::save_transactions_csv(&format!("{}.csv", file_path), transactions).expect("Failed to save transactions csv");
+    }
 
-    //     if cumulative_qty >= DOLLAR_BAR_LIMIT {
-    //         dollar_bars.push(dollar_bar.clone());
-    //         volume_bars.push(volume_bar);
-    //         pnl_bars.push(pnl_bar);
-    //         dollar_bar = DollarBar::new();
-    //         volume_bar = VolumeBar::new();
-    //         pnl_bar = PnlBar::new();
-    //         cumulative_qty = 0.0;
-    //         is_init = true;
-    //     }
-    // }
+    // Save bar series
+    if let Some(dollar_bars) = &analysis.dollar_bars {
+        let file_path: String = format!("{}/{}_dollar_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, dollar_bars).expect("Failed to save dollar bars json");
+    }
+    if let Some(base_volume_bars) = &analysis.base_volume_bars {
+        let file_path: String = format!("{}/{}_base_volume_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, base_volume_bars).expect("Failed to save base volume bars json");
+    }
+    if let Some(volume_bars) = &analysis.volume_bars {
+        let file_path: String = format!("{}/{}_volume_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, volume_bars).expect("Failed to save volume bars json");
+    }
+    if let Some(pnl_bars) = &analysis.pnl_bars {
+        let file_path: String = format!("{}/{}_pnl_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, pnl_bars).expect("Failed to save pnl bars json");
+    }
+    if let Some(time_bars) = &analysis.time_bars {
+        let file_path: String = format!("{}/{}_time_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, time_bars).expect("Failed to save time bars json");
+    }
+    if let Some(tick_bars) = &analysis.tick_bars {
+        let file_path: String = format!("{}/{}_tick_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, tick_bars).expect("Failed to save tick bars json");
+    }
+    if let Some(imbalance_bars) = &analysis.imbalance_bars {
+        let file_path: String = format!("{}/{}_imbalance_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, imbalance_bars).expect("Failed to save imbalance bars json");
+    }
+    if let Some(run_bars) = &analysis.run_bars {
+        let file_path: String = format!("{}/{}_run_bars.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, run_bars).expect("Failed to save run bars json");
+    }
 
+    // Save trader profiles
+    if let Some(mut trader_profiles) = analysis.trader_profiles {
+        report::sort_trader_profiles(&mut trader_profiles, TRADER_PROFILE_SORT_COLUMN, TRADER_PROFILE_SORT_DESCENDING).expect("Failed to sort trader profiles");
+        let file_path: String = format!("{}/{}_trader_profiles", WORKING_DIR, POOL);
+        exporter::save_json(&format!("{}.json", file_path), &trader_profiles).expect("Failed to save trader profiles json");
+        report::save_trader_profiles_csv(&format!("{}.csv", file_path), &trader_profiles).expect("Failed to save trader profiles csv");
+    }
 
+    // Save sandwich and arbitrage attacks
+    if let Some(mev_attacks) = &analysis.mev_attacks {
+        let file_path: String = format!("{}/{}_mev_attacks.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, mev_attacks).expect("Failed to save mev attacks json");
+    }
 
-    // // Save to csv
-    // let file_path = "/Users/shaun/Code/DEVELOPMENT/degentest/prices.csv";
-    // let file = std::fs::File::create(file_path).unwrap();
-    // let mut wtr = csv::Writer::from_writer(file);
+    // Save wallet clusters and entity level profiles
+    if let Some(wallet_clusters) = &analysis.wallet_clusters {
+        let file_path: String = format!("{}/{}_wallet_clusters.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, wallet_clusters).expect("Failed to save wallet clusters json");
+    }
+    if let Some(mut entity_profiles) = analysis.entity_profiles {
+        report::sort_trader_profiles(&mut entity_profiles, TRADER_PROFILE_SORT_COLUMN, TRADER_PROFILE_SORT_DESCENDING).expect("Failed to sort entity profiles");
+        let file_path: String = format!("{}/{}_entity_profiles", WORKING_DIR, POOL);
+        exporter::save_json(&format!("{}.json", file_path), &entity_profiles).expect("Failed to save entity profiles json");
+        report::save_trader_profiles_csv(&format!("{}.csv", file_path), &entity_profiles).expect("Failed to save entity profiles csv");
+    }
 
-    // // Writing the column headings
-    // wtr.write_record(&[
-    //     "Block", 
-    //     "Block Time", 
-    //     "Transaction", 
-    //     "Side",
-    //     "Account", 
-    //     "Amount Base", 
-    //     "Amount Quote", 
-    //     "Volume Buy",
-    //     "Volume Sell",
-    //     "Internal Realized PnLs",
-    //     "External Realized PnLs",
-    //     "Unrealized PnLs",
-    //     "Open Interest",
-    //     "Prices"
-    // ]).unwrap();
+    // Save liquidity events, reserve series and rug alerts
+    if let Some(liquidity_events) = &analysis.liquidity_events {
+        let file_path: String = format!("{}/{}_liquidity_events.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, liquidity_events).expect("Failed to save liquidity events json");
+    }
+    if let Some(reserve_series) = &analysis.reserve_series {
+        let file_path: String = format!("{}/{}_reserves.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, reserve_series).expect("Failed to save reserve series json");
+    }
+    if let Some(rug_alerts) = &analysis.rug_alerts {
+        let file_path: String = format!("{}/{}_rug_alerts.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, rug_alerts).expect("Failed to save rug alerts json");
+    }
 
-    // for (((((((((((((
-    //     &ref block, 
-    //     &ref block_time), 
-    //     &ref transaction), 
-    //     &ref side), 
-    //     &ref account), 
-    //     &ref amount_base), 
-    //     &ref amount_quote), 
-    //     &ref volume_buy), 
-    //     &ref volume_sell), 
-    //     &ref int_realized_pnl), 
-    //     &ref ext_realized_pnl), 
-    //     &ref unrealized_pnl), 
-    //     &ref open_interest), 
-    //     &ref prices
-    // ) 
-    // in blocks.iter()
-    //     .zip(block_times.iter())
-    //     .zip(transactions.iter())
-    //     .zip(sides.iter())
-    //     .zip(accounts.iter())
-    //     .zip(amounts_base.iter())
-    //     .zip(amounts_quote.iter())
-    //     .zip(volumes_quote_buy.iter())
-    //     .zip(volumes_quote_sell.iter())
-    //     .zip(internal_realized_pnls.iter())
-    //     .zip(external_realized_pnls.iter())
-    //     .zip(unrealized_pnls.iter()) 
-    //     .zip(open_interests.iter()) 
-    //     .zip(prices.iter()) 
-    // {
-    //     wtr.write_record(&[
-    //         block, 
-    //         block_time, 
-    //         transaction, 
-    //         side, 
-    //         &account, 
-    //         &amount_base.to_string(), 
-    //         &amount_quote.to_string(), 
-    //         &volume_buy.to_string(), 
-    //         &volume_sell.to_string(), 
-    //         &int_realized_pnl.to_string(), 
-    //         &ext_realized_pnl.to_string(), 
-    //         &unrealized_pnl.to_string(),
-    //         &open_interest.to_string(),
-    //         &prices.to_string(),
-    //     ]).unwrap();
-    // }
-    // wtr.flush().unwrap();
+    // Save holder distribution per snapshot bar
+    if let Some(holder_distributions) = &analysis.holder_distributions {
+        let file_path: String = format!("{}/{}_holder_distributions.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, holder_distributions).expect("Failed to save holder distributions json");
+    }
 
+    // Save closed lot journal per wallet
+    if CRITERIA.is_trader_profiles {
+        let closed_lots: HashMap<&String, &Vec<PositionClosed>> = processor.address_records_hm.iter()
+            .map(|(address, record)| (address, &record.positions_closed))
+            .collect();
+        let file_path: String = format!("{}/{}_closed_lots.json", WORKING_DIR, POOL);
+        exporter::save_json(&file_path, &closed_lots).expect("Failed to save closed lots json");
+    }
 }
@@ -0,0 +1,142 @@
+use crate::models::general::{MevAttack, MevKind, Side, TradeTx};
+
+/// Mev Detector
+/// Buffers each block's trades and, once the block is complete, orders them by transaction index
+/// to find sandwiches (front-run, victims on the same side, back-run by the same sender) and
+/// same-block buy-sell arbitrage by one sender with no victims in between
+/// Trades must arrive in block order, as they do from every data source
+#[derive(Debug, Default, Clone)]
+pub struct MevDetector {
+  block_trades: Vec<TradeTx>,
+}
+
+impl MevDetector {
+  pub fn new() -> Self {
+    Self { block_trades: vec![] }
+  }
+
+  /// Observe
+  /// Buffers a trade and returns the attacks found in the previous block once a new block starts
+  pub fn observe(&mut self, trade_tx: &TradeTx) -> Vec<MevAttack> {
+    let is_new_block: bool = self.block_trades.first().is_some_and(|first| first.block_num != trade_tx.block_num);
+    let attacks: Vec<MevAttack> = if is_new_block { self.flush() } else { vec![] };
+    self.block_trades.push(trade_tx.clone());
+    attacks
+  }
+
+  /// Flush
+  /// Returns the attacks found in the buffered block
+  pub fn flush(&mut self) -> Vec<MevAttack> {
+    let mut trades: Vec<TradeTx> = std::mem::take(&mut self.block_trades);
+    trades.sort_by_key(|trade_tx| trade_tx.tx_index);
+    detect_block_attacks(&trades)
+  }
+}
+
+/// Detect Block Attacks
+/// Pairs each trade with the sender's next opposite-side trade in the block,
+/// a sandwich if other senders traded on the opening side in between and arbitrage otherwise
+/// Only round trips that profit after the swap fees on both legs are reported
+pub fn detect_block_attacks(trades: &[TradeTx]) -> Vec<MevAttack> {
+  let mut used: Vec<bool> = vec![false; trades.len()];
+  let mut attacks: Vec<MevAttack> = vec![];
+  for front_idx in 0..trades.len() {
+    if used[front_idx] { continue; }
+    let front: &TradeTx = &trades[front_idx];
+    let back_idx: Option<usize> = (front_idx + 1..trades.len())
+      .find(|idx| !used[*idx] && trades[*idx].account_addr == front.account_addr && trades[*idx].side != front.side);
+    let Some(back_idx) = back_idx else { continue };
+    let back: &TradeTx = &trades[back_idx];
+
+    let victims: Vec<&TradeTx> = trades[front_idx + 1..back_idx].iter()
+      .filter(|victim| victim.account_addr != front.account_addr && victim.side == front.side)
+      .collect();
+    let (buy, sell) = if front.side == Side::Buy { (front, back) } else { (back, front) };
+    let matched_base: f64 = buy.volume_base.min(sell.volume_base);
+    let matched_swap_fees: f64 = buy.swap_fee_quote * matched_base / buy.volume_base + sell.swap_fee_quote * matched_base / sell.volume_base;
+    let profit_quote: f64 = (sell.price_quote - buy.price_quote) * matched_base - matched_swap_fees;
+    if profit_quote <= 0.0 { continue; }
+
+    attacks.push(MevAttack {
+      kind: if victims.is_empty() { MevKind::Arbitrage } else { MevKind::Sandwich },
+      block_num: front.block_num,
+      attacker_addr: front.account_addr.clone(),
+      front_run_tx: front.tx_hash.clone(),
+      back_run_tx: back.tx_hash.clone(),
+      victim_txs: victims.iter().map(|victim| victim.tx_hash.clone()).collect(),
+      victim_addrs: victims.iter().map(|victim| victim.account_addr.clone()).collect(),
+      matched_base,
+      profit_quote
+    });
+    used[front_idx] = true;
+    used[back_idx] = true;
+  }
+  attacks
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_detects_a_sandwich_out_of_order() {
+    let mut detector: MevDetector = MevDetector::new();
+    // Trades arrive unordered within the block
+    assert!(detector.observe(&TradeTx::test_trade(10, Side::Sell, "0xbot", 100.0, 1.2).at_index(3)).is_empty());
+    assert!(detector.observe(&TradeTx::test_trade(10, Side::Buy, "0xbot", 100.0, 1.0).at_index(1)).is_empty());
+    assert!(detector.observe(&TradeTx::test_trade(10, Side::Buy, "0xvictim", 50.0, 1.1).at_index(2)).is_empty());
+    assert!(detector.observe(&TradeTx::test_trade(10, Side::Buy, "0xother", 10.0, 1.2).at_index(4)).is_empty());
+
+    let mut next_block: TradeTx = TradeTx::test_trade(10, Side::Buy, "0xa", 1.0, 1.0).at_index(1);
+    next_block.block_num = 11;
+    let attacks: Vec<MevAttack> = detector.observe(&next_block);
+    assert_eq!(attacks.len(), 1);
+    let attack: &MevAttack = &attacks[0];
+    assert_eq!(attack.kind, MevKind::Sandwich);
+    assert_eq!(attack.attacker_addr, "0xbot");
+    assert_eq!((attack.front_run_tx.as_str(), attack.back_run_tx.as_str()), ("0x1", "0x3"));
+    assert_eq!(attack.victim_txs, vec!["0x2".to_string()]);
+    assert!((attack.profit_quote - 20.0).abs() < 1e-9);
+    assert!(detector.flush().is_empty());
+  }
+
+  #[test]
+  fn it_detects_sell_side_sandwich_and_arbitrage() {
+    let attacks: Vec<MevAttack> = detect_block_attacks(&[
+      TradeTx::test_trade(10, Side::Sell, "0xbot", 10.0, 1.5).at_index(1),
+      TradeTx::test_trade(10, Side::Sell, "0xa", 10.0, 1.4).at_index(2),
+      TradeTx::test_trade(10, Side::Buy, "0xbot", 8.0, 1.0).at_index(3)
+    ]);
+    assert_eq!(attacks.len(), 1);
+    assert_eq!(attacks[0].kind, MevKind::Sandwich);
+    // Sold 8 of the 10 back at 0.5 below the front-run price
+    assert_eq!(attacks[0].matched_base, 8.0);
+    assert!((attacks[0].profit_quote - 4.0).abs() < 1e-9);
+
+    let attacks: Vec<MevAttack> = detect_block_attacks(&[
+      TradeTx::test_trade(10, Side::Buy, "0xarb", 10.0, 1.0).at_index(1),
+      TradeTx::test_trade(10, Side::Sell, "0xarb", 10.0, 1.1).at_index(1)
+    ]);
+    assert_eq!(attacks[0].kind, MevKind::Arbitrage);
+    assert!((attacks[0].profit_quote - 1.0).abs() < 1e-9);
+  }
+
+  #[test]
+  fn it_skips_round_trips_unprofitable_after_swap_fees() {
+    let with_fee = |trade_tx: TradeTx| TradeTx { swap_fee_quote: trade_tx.volume_quote * 0.003, ..trade_tx };
+    // Gains 0.5 on the price but pays about 0.6 in swap fees across both legs
+    let attacks: Vec<MevAttack> = detect_block_attacks(&[
+      with_fee(TradeTx::test_trade(10, Side::Buy, "0xbot", 100.0, 1.0).at_index(1)),
+      with_fee(TradeTx::test_trade(10, Side::Buy, "0xvictim", 50.0, 1.0).at_index(2)),
+      with_fee(TradeTx::test_trade(10, Side::Sell, "0xbot", 100.0, 1.005).at_index(3))
+    ]);
+    assert!(attacks.is_empty());
+
+    // A losing round trip is not reported even without fees
+    let attacks: Vec<MevAttack> = detect_block_attacks(&[
+      TradeTx::test_trade(10, Side::Buy, "0xarb", 10.0, 1.1).at_index(1),
+      TradeTx::test_trade(10, Side::Sell, "0xarb", 10.0, 1.0).at_index(2)
+    ]);
+    assert!(attacks.is_empty());
+  }
+}
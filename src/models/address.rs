@@ -1,50 +1,232 @@
+use crate::models::general::{Side, TradeTx};
 use serde::{Deserialize, Serialize};
 
+/// Share of a lot's purchased quantity below which its remainder is float dust and the lot is closed
+const LOT_DUST_FRACTION: f64 = 1e-9;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PositionOpen {
   pub purchase_amount_base_qty: f64,
   pub remaining_amount_base: f64,
   pub purchase_price_quote: f64,
+  #[serde(default)]
+  pub swap_fee_quote_per_base: f64,
+  #[serde(default)]
+  pub gas_fee_quote_per_base: f64,
+  #[serde(default)]
+  pub open_block_num: u64,
+  #[serde(default)]
+  pub open_timestamp: i64,
 }
 
+/// A closed lot, the quantity of one open position matched by a sell
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PositionClosed {
   pub selling_amount_base_qty: f64,
   pub selling_price_quote: f64,
+  pub purchase_price_quote: f64,
+  pub open_block_num: u64,
+  pub close_block_num: u64,
+  pub open_timestamp: i64,
+  pub close_timestamp: i64,
+  pub realized_pnl: f64,
+  pub holding_secs: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+impl PositionClosed {
+  /// From Lot
+  /// Journals the quantity closed out of an open lot, costed at the entry price the cost basis method applied
+  pub fn from_lot(open_pos: &PositionOpen, closed_qty: f64, selling_price_quote: f64, closed_at: (u64, i64), purchase_price_quote: f64) -> Self {
+    let (close_block_num, close_timestamp) = closed_at;
+    Self {
+      selling_amount_base_qty: closed_qty,
+      selling_price_quote,
+      purchase_price_quote,
+      open_block_num: open_pos.open_block_num,
+      close_block_num,
+      open_timestamp: open_pos.open_timestamp,
+      close_timestamp,
+      realized_pnl: (selling_price_quote - purchase_price_quote) * closed_qty,
+      holding_secs: (close_timestamp - open_pos.open_timestamp).max(0)
+    }
+  }
+}
+
+/// Cost Basis Method
+/// Which open positions a sell is matched against when realizing pnl
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum CostBasisMethod {
+  #[default]
+  Fifo,
+  Lifo,
+  AverageCost,
+  Hifo
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct AddressRecords {
+  pub cost_basis_method: CostBasisMethod,
   pub positions_open: Vec<PositionOpen>,
   pub positions_closed: Vec<PositionClosed>,
   pub count_profit: u64,
   pub count_loss: u64,
+  pub realized_entry_swap_fees_quote: f64,
+  pub realized_entry_gas_fees_quote: f64,
+  pub realized_internal_pnl: f64,
+  pub realized_external_pnl: f64,
+  pub first_block: u64,
+  pub last_block: u64,
+  pub count_buys: u64,
+  pub count_sells: u64,
+  pub quote_in: f64,
+  pub quote_out: f64,
+  pub closed_base_qty: f64,
+  pub holding_base_secs: f64,
+  pub largest_win: f64,
+  pub largest_loss: f64,
 }
 
 impl AddressRecords {
   pub fn new() -> Self {
     Self { 
+      cost_basis_method: CostBasisMethod::Fifo,
       positions_open: vec![], 
       positions_closed: vec![],
       count_profit: 0, 
-      count_loss: 0 
+      count_loss: 0,
+      realized_entry_swap_fees_quote: 0.0,
+      realized_entry_gas_fees_quote: 0.0,
+      realized_internal_pnl: 0.0,
+      realized_external_pnl: 0.0,
+      first_block: 0,
+      last_block: 0,
+      count_buys: 0,
+      count_sells: 0,
+      quote_in: 0.0,
+      quote_out: 0.0,
+      closed_base_qty: 0.0,
+      holding_base_secs: 0.0,
+      largest_win: 0.0,
+      largest_loss: 0.0
+    }
+  }
+
+  pub fn with_cost_basis(cost_basis_method: CostBasisMethod) -> Self {
+    Self { cost_basis_method, ..Self::new() }
+  }
+
+  /// Lot Order
+  /// Returns open position indexes in the order a sell consumes them
+  fn lot_order(&self) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..self.positions_open.len()).collect();
+    match self.cost_basis_method {
+      CostBasisMethod::Fifo | CostBasisMethod::AverageCost => {},
+      CostBasisMethod::Lifo => order.reverse(),
+      CostBasisMethod::Hifo => order.sort_by(|a, b| {
+        self.positions_open[*b].purchase_price_quote.total_cmp(&self.positions_open[*a].purchase_price_quote)
+      })
+    }
+    order
+  }
+
+  /// Average Cost
+  /// Returns the weighted average purchase price of the remaining open quantity
+  pub fn average_cost(&self) -> f64 {
+    let open_interest: f64 = self.get_open_interest();
+    if open_interest <= 0.0 { return 0.0 }
+    let cost: f64 = self.positions_open.iter().map(|open_pos| open_pos.purchase_price_quote * open_pos.remaining_amount_base).sum();
+    cost / open_interest
+  }
+
+  /// Record Trade
+  /// Counts a trade towards the address's activity, whichever side it was on
+  pub fn record_trade(&mut self, trade_tx: &TradeTx) {
+    if self.count_buys + self.count_sells == 0 { self.first_block = trade_tx.block_num; }
+    self.last_block = trade_tx.block_num;
+    match trade_tx.side {
+      Side::Buy => {
+        self.count_buys += 1;
+        self.quote_in += trade_tx.volume_quote;
+      },
+      Side::Sell => {
+        self.count_sells += 1;
+        self.quote_out += trade_tx.volume_quote;
+      }
+    }
+  }
+
+  /// Win Rate
+  /// Returns the share of closing sells that were profitable
+  pub fn win_rate(&self) -> f64 {
+    let closes: u64 = self.count_profit + self.count_loss;
+    if closes == 0 { 0.0 } else { self.count_profit as f64 / closes as f64 }
+  }
+
+  /// Average Holding Secs
+  /// Returns the average time closed quantity was held, weighted by quantity
+  pub fn average_holding_secs(&self) -> f64 {
+    if self.closed_base_qty <= 0.0 { 0.0 } else { self.holding_base_secs / self.closed_base_qty }
+  }
+
+  /// Open Position From
+  /// Adds an open position for a buy, stamped with its block and time
+  pub fn open_position_from(&mut self, trade_tx: &TradeTx) {
+    self.open_position_with_fees(trade_tx.volume_base, trade_tx.price_quote, trade_tx.swap_fee_quote, trade_tx.gas_fee_quote);
+    if let Some(position) = self.positions_open.last_mut() {
+      position.open_block_num = trade_tx.block_num;
+      position.open_timestamp = trade_tx.block_timestamp;
     }
   }
 
   /// Open Position
   /// Adds an open position
   pub fn open_position(&mut self, purchase_amount_base_qty: f64, purchase_price_quote: f64) {
+    self.open_position_with_fees(purchase_amount_base_qty, purchase_price_quote, 0.0, 0.0);
+  }
+
+  /// Open Position With Fees
+  /// Adds an open position carrying the swap fee and gas paid to open it, spread per unit of base
+  pub fn open_position_with_fees(&mut self, purchase_amount_base_qty: f64, purchase_price_quote: f64, swap_fee_quote: f64, gas_fee_quote: f64) {
+    let per_base = |fee_quote: f64| if purchase_amount_base_qty > 0.0 { fee_quote / purchase_amount_base_qty } else { 0.0 };
     let position: PositionOpen = PositionOpen{ 
       purchase_amount_base_qty,
       remaining_amount_base: purchase_amount_base_qty,
-      purchase_price_quote 
+      purchase_price_quote,
+      swap_fee_quote_per_base: per_base(swap_fee_quote),
+      gas_fee_quote_per_base: per_base(gas_fee_quote),
+      open_block_num: 0,
+      open_timestamp: 0
     };
     self.positions_open.push(position);
   }
 
+  /// Get Cost Basis
+  /// Returns the quote paid for the remaining quantity, so unrealized pnl at a price is price * open interest - cost basis
+  pub fn get_cost_basis(&self) -> f64 {
+    if self.cost_basis_method == CostBasisMethod::AverageCost {
+      return self.average_cost() * self.get_open_interest();
+    }
+    self.positions_open.iter().map(|open_pos| open_pos.purchase_price_quote * open_pos.remaining_amount_base).sum()
+  }
+
+  /// Get Open Fees
+  /// Returns the swap fees and gas paid to open the remaining quantity
+  pub fn get_open_fees(&self) -> (f64, f64) {
+    let mut swap_fees_quote = 0.0;
+    let mut gas_fees_quote = 0.0;
+    for open_pos in &self.positions_open {
+      swap_fees_quote += open_pos.swap_fee_quote_per_base * open_pos.remaining_amount_base;
+      gas_fees_quote += open_pos.gas_fee_quote_per_base * open_pos.remaining_amount_base;
+    }
+    (swap_fees_quote, gas_fees_quote)
+  }
+
   /// Calculate Unrealized PnL
-  /// Calculates unrealized profit and loss
+  /// Calculates unrealized profit and loss against each remaining position, or the average cost
   pub fn calculate_unrealized_position(&self, current_quote_price: f64) -> f64 {
+    if self.cost_basis_method == CostBasisMethod::AverageCost {
+      return (current_quote_price - self.average_cost()) * self.get_open_interest();
+    }
     let mut unrealized_pnl = 0.0;
     for open_pos in &self.positions_open {
       if open_pos.remaining_amount_base != 0.0 {
@@ -68,7 +250,7 @@ impl AddressRecords {
   }
 
   /// Get Open Interest
-  /// Returns open interest
+  /// Returns open interest, the remaining quantity left by whichever positions the cost basis method closed
   pub fn get_open_interest(&self) -> f64 {
     let mut open_interest = 0.0;
     for open_pos in &self.positions_open {
@@ -78,9 +260,45 @@ impl AddressRecords {
   }
 
   /// Close Position
-  /// Closes any open positions and increments realized pnl
+  /// Closes open positions in the order given by the cost basis method and increments realized pnl
+  /// Any quantity sold beyond the open positions is booked as external pnl against external_cost_quote per unit
+  /// The fees paid to open the closed quantity are added to realized_entry_swap_fees_quote and realized_entry_gas_fees_quote
   /// Returns realized pnl and external pnl
-  pub fn close_positions(&mut self, sell_base_qty: f64, sell_quote_price: f64) -> (f64, f64) {
+  pub fn close_positions(&mut self, sell_base_qty: f64, sell_quote_price: f64, external_cost_quote: f64) -> (f64, f64) {
+    self.close_positions_at(sell_base_qty, sell_quote_price, external_cost_quote, (0, 0))
+  }
+
+  /// Close Positions From
+  /// Closes positions for a sell, measuring holding time up to its block time
+  pub fn close_positions_from(&mut self, trade_tx: &TradeTx, external_cost_quote: f64) -> (f64, f64) {
+    self.close_positions_at(trade_tx.volume_base, trade_tx.price_quote, external_cost_quote, (trade_tx.block_num, trade_tx.block_timestamp))
+  }
+
+  /// Closes positions at the (block, timestamp) of the sell, journaling each closed lot and dropping lots left empty
+  /// A sell that closed any quantity counts once as a win or loss on its internal pnl, whichever lots it consumed
+  fn close_positions_at(&mut self, sell_base_qty: f64, sell_quote_price: f64, external_cost_quote: f64, closed_at: (u64, i64)) -> (f64, f64) {
+    let open_interest: f64 = self.get_open_interest();
+    let (internal_pnl, external_pnl) = if self.cost_basis_method == CostBasisMethod::AverageCost {
+      self.close_positions_average(sell_base_qty, sell_quote_price, external_cost_quote, closed_at)
+    } else {
+      self.close_positions_by_lot(sell_base_qty, sell_quote_price, external_cost_quote, closed_at)
+    };
+    if open_interest > 0.0 && sell_base_qty > 0.0 {
+      if internal_pnl > 0.0 { self.count_profit += 1 };
+      if internal_pnl < 0.0 { self.count_loss += 1 };
+      self.largest_win = self.largest_win.max(internal_pnl);
+      self.largest_loss = self.largest_loss.min(internal_pnl);
+    }
+    self.positions_open.retain(|open_pos| open_pos.remaining_amount_base > open_pos.purchase_amount_base_qty * LOT_DUST_FRACTION);
+    self.realized_internal_pnl += internal_pnl;
+    self.realized_external_pnl += external_pnl;
+    (internal_pnl, external_pnl)
+  }
+
+  /// Close Positions By Lot
+  /// Closes whole or partial positions one at a time in lot order
+  fn close_positions_by_lot(&mut self, sell_base_qty: f64, sell_quote_price: f64, external_cost_quote: f64, closed_at: (u64, i64)) -> (f64, f64) {
+    let close_timestamp: i64 = closed_at.1;
     let mut remaining_sell_qty: f64 = sell_base_qty;
 
     // Handle if Open Positions exist
@@ -88,8 +306,21 @@ impl AddressRecords {
     // If only partial close of a position (fully utilize remaining_sell_qty)
     let mut internal_pnl: f64 = 0.0;
     let mut external_pnl: f64 = 0.0;
-    for open_pos in &mut self.positions_open {
-      
+    let mut entry_swap_fees_quote: f64 = 0.0;
+    let mut entry_gas_fees_quote: f64 = 0.0;
+    let mut closed_base_qty: f64 = 0.0;
+    let mut holding_base_secs: f64 = 0.0;
+    for idx in self.lot_order() {
+      if remaining_sell_qty <= 0.0 { break; }
+      let open_pos: &mut PositionOpen = &mut self.positions_open[idx];
+      if open_pos.remaining_amount_base <= 0.0 { continue; }
+
+      let closed_qty: f64 = remaining_sell_qty.min(open_pos.remaining_amount_base);
+      entry_swap_fees_quote += open_pos.swap_fee_quote_per_base * closed_qty;
+      entry_gas_fees_quote += open_pos.gas_fee_quote_per_base * closed_qty;
+      closed_base_qty += closed_qty;
+      holding_base_secs += closed_qty * (close_timestamp - open_pos.open_timestamp).max(0) as f64;
+
       let open_pos_pnl: f64;
       if remaining_sell_qty >= open_pos.remaining_amount_base {
         open_pos_pnl = (sell_quote_price * open_pos.remaining_amount_base) - (open_pos.purchase_price_quote * open_pos.remaining_amount_base);
@@ -101,17 +332,162 @@ impl AddressRecords {
         remaining_sell_qty = 0.0;
       }
 
-      if open_pos_pnl > 0.0 { self.count_profit += 1 };
-      if open_pos_pnl < 0.0 { self.count_loss += 1 };
       internal_pnl += open_pos_pnl;
+      let position_closed: PositionClosed = PositionClosed::from_lot(open_pos, closed_qty, sell_quote_price, closed_at, open_pos.purchase_price_quote);
+      self.positions_closed.push(position_closed);
     }
+    self.realized_entry_swap_fees_quote += entry_swap_fees_quote;
+    self.realized_entry_gas_fees_quote += entry_gas_fees_quote;
+    self.closed_base_qty += closed_base_qty;
+    self.holding_base_secs += holding_base_secs;
 
     // Handle if Sell Quantity still has remaining value (probably a dump of tokens or arbitrage trade from another pool)
     if remaining_sell_qty > 0.0 {
-      external_pnl += sell_quote_price * remaining_sell_qty;
+      external_pnl += (sell_quote_price - external_cost_quote) * remaining_sell_qty;
     }
 
     // Return internal and external pnl
     (internal_pnl, external_pnl)
   }
+
+  /// Close Positions Average
+  /// Closes against the weighted average cost, reducing every open position pro rata so the average is unchanged
+  fn close_positions_average(&mut self, sell_base_qty: f64, sell_quote_price: f64, external_cost_quote: f64, closed_at: (u64, i64)) -> (f64, f64) {
+    let close_timestamp: i64 = closed_at.1;
+    let open_interest: f64 = self.get_open_interest();
+    let mut internal_pnl: f64 = 0.0;
+    let mut external_pnl: f64 = 0.0;
+    if open_interest > 0.0 {
+      let closed_qty: f64 = sell_base_qty.min(open_interest);
+      let average_cost: f64 = self.average_cost();
+      internal_pnl = (sell_quote_price - average_cost) * closed_qty;
+      let remaining_fraction: f64 = 1.0 - closed_qty / open_interest;
+      let (open_swap_fees_quote, open_gas_fees_quote) = self.get_open_fees();
+      self.realized_entry_swap_fees_quote += open_swap_fees_quote * (1.0 - remaining_fraction);
+      self.realized_entry_gas_fees_quote += open_gas_fees_quote * (1.0 - remaining_fraction);
+      for open_pos in &mut self.positions_open {
+        let remaining_amount_base: f64 = if remaining_fraction > 0.0 { open_pos.remaining_amount_base * remaining_fraction } else { 0.0 };
+        let lot_closed_qty: f64 = open_pos.remaining_amount_base - remaining_amount_base;
+        self.closed_base_qty += lot_closed_qty;
+        self.holding_base_secs += lot_closed_qty * (close_timestamp - open_pos.open_timestamp).max(0) as f64;
+        if lot_closed_qty > 0.0 {
+          self.positions_closed.push(PositionClosed::from_lot(open_pos, lot_closed_qty, sell_quote_price, closed_at, average_cost));
+        }
+        open_pos.remaining_amount_base = remaining_amount_base;
+      }
+    }
+
+    // Handle if Sell Quantity still has remaining value (probably a dump of tokens or arbitrage trade from another pool)
+    if sell_base_qty > open_interest {
+      external_pnl += (sell_quote_price - external_cost_quote) * (sell_base_qty - open_interest);
+    }
+    (internal_pnl, external_pnl)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn records(cost_basis_method: CostBasisMethod) -> AddressRecords {
+    let mut records: AddressRecords = AddressRecords::with_cost_basis(cost_basis_method);
+    records.open_position(10.0, 1.0);
+    records.open_position(10.0, 3.0);
+    records.open_position(10.0, 2.0);
+    records
+  }
+
+  #[test]
+  fn it_closes_by_cost_basis_method() {
+    let expected: [(CostBasisMethod, f64, f64); 4] = [
+      (CostBasisMethod::Fifo, 20.0, 25.0),
+      (CostBasisMethod::Lifo, 10.0, 35.0),
+      (CostBasisMethod::AverageCost, 15.0, 30.0),
+      (CostBasisMethod::Hifo, 5.0, 40.0)
+    ];
+    for (cost_basis_method, realized_pnl, unrealized_pnl) in expected {
+      let mut records: AddressRecords = records(cost_basis_method);
+      let (internal_pnl, external_pnl) = records.close_positions(15.0, 3.0, 0.0);
+      assert_eq!(internal_pnl, realized_pnl, "{:?}", cost_basis_method);
+      assert_eq!(external_pnl, 0.0);
+      assert_eq!(records.get_open_interest(), 15.0, "{:?}", cost_basis_method);
+      assert!((records.calculate_unrealized_position(4.0) - unrealized_pnl).abs() < 1e-9, "{:?}", cost_basis_method);
+    }
+  }
+
+  #[test]
+  fn it_books_oversold_quantity_as_external() {
+    let mut records: AddressRecords = records(CostBasisMethod::AverageCost);
+    let (internal_pnl, external_pnl) = records.close_positions(40.0, 3.0, 0.0);
+    assert_eq!(internal_pnl, 30.0);
+    assert_eq!(external_pnl, 30.0);
+
+    let mut records: AddressRecords = AddressRecords::new();
+    let (_, external_pnl) = records.close_positions(10.0, 3.0, 2.0);
+    assert_eq!(external_pnl, 10.0);
+    assert_eq!(records.get_open_interest(), 0.0);
+    assert_eq!(records.count_open_positions(), 0);
+  }
+
+  #[test]
+  fn it_realizes_entry_fees_with_closed_quantity() {
+    for cost_basis_method in [CostBasisMethod::Fifo, CostBasisMethod::AverageCost] {
+      let mut records: AddressRecords = AddressRecords::with_cost_basis(cost_basis_method);
+      records.open_position_with_fees(10.0, 1.0, 0.03, 0.5);
+      records.close_positions(4.0, 2.0, 0.0);
+      assert!((records.realized_entry_swap_fees_quote - 0.012).abs() < 1e-12, "{:?}", cost_basis_method);
+      assert!((records.realized_entry_gas_fees_quote - 0.2).abs() < 1e-12, "{:?}", cost_basis_method);
+      let (open_swap_fees_quote, open_gas_fees_quote) = records.get_open_fees();
+      assert!((open_swap_fees_quote - 0.018).abs() < 1e-12);
+      assert!((open_gas_fees_quote - 0.3).abs() < 1e-12);
+    }
+  }
+
+  #[test]
+  fn it_journals_closed_lots() {
+    let mut records: AddressRecords = AddressRecords::new();
+    for (block_num, price_quote) in [(1, 1.0), (2, 3.0)] {
+      records.open_position_from(&TradeTx::test_trade(block_num, Side::Buy, "0xa", 10.0, price_quote));
+    }
+    records.close_positions_from(&TradeTx::test_trade(5, Side::Sell, "0xa", 15.0, 2.0), 0.0);
+
+    assert_eq!(records.positions_open.len(), 1);
+    assert_eq!(records.positions_open[0].open_block_num, 2);
+    assert_eq!(records.positions_closed.len(), 2);
+    let first: &PositionClosed = &records.positions_closed[0];
+    assert_eq!((first.open_block_num, first.close_block_num), (1, 5));
+    assert_eq!((first.selling_amount_base_qty, first.purchase_price_quote, first.selling_price_quote), (10.0, 1.0, 2.0));
+    assert_eq!((first.realized_pnl, first.holding_secs), (10.0, 240));
+    let second: &PositionClosed = &records.positions_closed[1];
+    assert_eq!((second.selling_amount_base_qty, second.realized_pnl, second.holding_secs), (5.0, -5.0, 180));
+  }
+
+  #[test]
+  fn it_counts_wins_per_closing_sell() {
+    for cost_basis_method in [CostBasisMethod::Fifo, CostBasisMethod::Lifo, CostBasisMethod::AverageCost, CostBasisMethod::Hifo] {
+      let mut records: AddressRecords = records(cost_basis_method);
+      // Every method nets a gain selling all 30 at 2.5, whether or not single lots lose
+      records.close_positions(30.0, 2.5, 0.0);
+      assert_eq!((records.count_profit, records.count_loss), (1, 0), "{:?}", cost_basis_method);
+      assert_eq!((records.largest_win, records.largest_loss), (15.0, 0.0), "{:?}", cost_basis_method);
+      // Selling with nothing open is external and not a close
+      records.close_positions(5.0, 1.0, 0.0);
+      assert_eq!((records.count_profit, records.count_loss), (1, 0), "{:?}", cost_basis_method);
+    }
+    // The journal still holds each lot's own result
+    let mut records: AddressRecords = records(CostBasisMethod::Fifo);
+    records.close_positions(30.0, 2.5, 0.0);
+    let lot_pnls: Vec<f64> = records.positions_closed.iter().map(|closed| closed.realized_pnl).collect();
+    assert_eq!(lot_pnls, vec![15.0, -5.0, 5.0]);
+  }
+
+  #[test]
+  fn it_drops_lots_left_with_float_dust() {
+    let mut records: AddressRecords = AddressRecords::new();
+    records.open_position(0.1, 1.0);
+    records.open_position(0.2, 1.0);
+    records.close_positions(0.1 + 0.2, 2.0, 0.0);
+    assert!(records.positions_open.is_empty());
+    assert_eq!(records.count_open_positions(), 0);
+  }
 }
@@ -1,9 +1,36 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct BitQueryData {
   pub data: HashMap<String, EVMData>,
+  #[serde(default)]
+  pub last_block: u64,
+}
+
+impl BitQueryData {
+  pub fn new() -> Self {
+    Self { data: HashMap::new(), last_block: 0 }
+  }
+
+  /// Highest Block
+  /// Returns the recorded highest block, falling back to scanning trades for caches saved without one
+  pub fn highest_block(&self) -> u64 {
+    if self.last_block > 0 { return self.last_block }
+    self.data.values()
+      .flat_map(|evm_data| evm_data.dex_trade_by_tokens.iter())
+      .filter_map(|t| t.block.number.parse::<u64>().ok())
+      .max()
+      .unwrap_or(0)
+  }
+
+  /// Has Log Indexes
+  /// Returns false for caches saved before log indexes were requested
+  pub fn has_log_indexes(&self) -> bool {
+    self.data.values()
+      .flat_map(|evm_data| evm_data.dex_trade_by_tokens.iter())
+      .all(|t| t.log.is_some())
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,7 +48,22 @@ pub struct TradeInfo {
   #[serde(rename = "Trade")]
   pub trade: Trade,
   #[serde(rename = "Transaction")]
-  pub transaction: Transaction
+  pub transaction: Transaction,
+  #[serde(rename = "Log", default, skip_serializing_if = "Option::is_none")]
+  pub log: Option<LogInfo>
+}
+
+impl TradeInfo {
+  /// Trade Key
+  /// Identifies a trade by transaction hash and log index
+  /// The log index tells apart the swaps of a transaction that routes through the pool several times
+  /// Trades without a log index fall back to the transaction index and traded amounts
+  pub fn trade_key(&self) -> String {
+    match &self.log {
+      Some(log) => format!("{}:{}", self.transaction.hash, log.index),
+      None => format!("{}:{}:{}:{}", self.transaction.hash, self.transaction.index, self.trade.amount, self.trade.side.amount)
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -47,7 +89,13 @@ pub struct Trade {
   #[serde(rename = "Seller")]
   pub seller: String,
   #[serde(rename = "Side")]
-  pub side: Side
+  pub side: Side,
+  /// V3 active liquidity after the swap in sqrt(base * quote) units, only known from swap logs
+  #[serde(rename = "PoolLiquidity", default, skip_serializing_if = "Option::is_none")]
+  pub pool_liquidity: Option<String>,
+  /// V3 pool price after the swap in quote per base, only known from swap logs
+  #[serde(rename = "PoolPrice", default, skip_serializing_if = "Option::is_none")]
+  pub pool_price: Option<String>
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -77,5 +125,30 @@ pub struct Transaction {
   #[serde(rename = "Hash")]
   pub hash: String,
   #[serde(rename = "From")]
-  pub from: String
+  pub from: String,
+  #[serde(rename = "Index", default, deserialize_with = "u64_from_str_or_num")]
+  pub index: u64,
+  #[serde(rename = "Gas", default, skip_serializing_if = "Option::is_none")]
+  pub gas: Option<String>,
+  #[serde(rename = "GasPrice", default, skip_serializing_if = "Option::is_none")]
+  pub gas_price: Option<String>,
+  #[serde(rename = "GasUsed", default, skip_serializing_if = "Option::is_none")]
+  pub gas_used: Option<String>
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct LogInfo {
+  #[serde(rename = "Index", default, deserialize_with = "u64_from_str_or_num")]
+  pub index: u64
+}
+
+/// Deserializes a u64 that BitQuery may return either as a JSON number or a string
+fn u64_from_str_or_num<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum StrOrNum { Str(String), Num(u64) }
+  match StrOrNum::deserialize(deserializer)? {
+    StrOrNum::Str(s) => s.parse::<u64>().map_err(serde::de::Error::custom),
+    StrOrNum::Num(n) => Ok(n)
+  }
 }
@@ -1,4 +1,4 @@
-use crate::models::traits::TimeBars;
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
@@ -7,97 +7,517 @@ pub enum Side {
   Sell
 }
 
-impl Side {
-  pub fn to_string(&self) -> String {
+impl std::fmt::Display for Side {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     match &self {
-      Side::Buy => "Buy".to_string(),
-      Side::Sell => "Sell".to_string()
+      Side::Buy => write!(f, "Buy"),
+      Side::Sell => write!(f, "Sell")
     }
   }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// OHLC bar sampled on traded notional (quote amount, or USD when a quote/USD series is supplied)
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct DollarBar {
   pub datetime: String,
   pub open: f64,
   pub high: f64,
   pub low: f64,
-  pub close: f64
+  pub close: f64,
+  pub notional: f64,
+  pub reserves: Option<ReservePoint>
 }
 
-impl TimeBars for DollarBar {
-  fn new() -> Self {
-    Self { datetime: "".to_string(), open: 0.0, high: 0.0, low: 0.0, close: 0.0 }
+impl DollarBar {
+  pub fn new() -> Self {
+    Self { datetime: "".to_string(), open: 0.0, high: 0.0, low: 0.0, close: 0.0, notional: 0.0, reserves: None }
   }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// OHLC bar sampled on traded base token quantity
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct BaseVolumeBar {
+  pub datetime: String,
+  pub open: f64,
+  pub high: f64,
+  pub low: f64,
+  pub close: f64,
+  pub volume_base: f64,
+  pub reserves: Option<ReservePoint>
+}
+
+impl BaseVolumeBar {
+  pub fn new() -> Self {
+    Self { datetime: "".to_string(), open: 0.0, high: 0.0, low: 0.0, close: 0.0, volume_base: 0.0, reserves: None }
+  }
+}
+
+/// Buy and sell quote volume, split into organic and suspected wash volume
+/// The wash score is the wash share of the bar's total volume
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct VolumeBar {
   pub datetime: String,
   pub volume_buys: f64,
   pub volume_sells: f64,
+  pub organic_volume_buys: f64,
+  pub organic_volume_sells: f64,
+  pub wash_volume_buys: f64,
+  pub wash_volume_sells: f64,
+  pub wash_score: f64,
+  pub reserves: Option<ReservePoint>,
 }
 
-impl TimeBars for VolumeBar {
-  fn new() -> Self { 
-    Self { datetime: "".to_string(), volume_buys: 0.0, volume_sells: 0.0 }
+impl VolumeBar {
+  pub fn new() -> Self { 
+    Self { datetime: "".to_string(), volume_buys: 0.0, volume_sells: 0.0, organic_volume_buys: 0.0, organic_volume_sells: 0.0,
+      wash_volume_buys: 0.0, wash_volume_sells: 0.0, wash_score: 0.0, reserves: None }
   }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Realized pnl and fees summed over the bar's trades, with unrealized pnl and open interest as at the bar's last trade
+/// Gross pnl is before swap fees and gas, net pnl after both
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct PnlBar {
   pub datetime: String,
   pub internal_realized_pnl: f64,
-  pub external_realized_pnl: f64
+  pub external_realized_pnl: f64,
+  pub realized_pnl_gross: f64,
+  pub realized_pnl_net: f64,
+  pub swap_fees_quote: f64,
+  pub gas_fees_quote: f64,
+  pub unrealized_pnl: f64,
+  pub unrealized_pnl_gross: f64,
+  pub unrealized_pnl_net: f64,
+  pub open_interest_base: f64,
+  pub trade_count: u64,
+  pub count_won: u64,
+  pub count_lost: u64,
+  pub reserves: Option<ReservePoint>
 }
 
-impl TimeBars for PnlBar {
-  fn new() -> Self { 
-    Self { datetime: "".to_string(), internal_realized_pnl: 0.0, external_realized_pnl: 0.0 }
+impl PnlBar {
+  pub fn new() -> Self { 
+    Self { datetime: "".to_string(), internal_realized_pnl: 0.0, external_realized_pnl: 0.0, realized_pnl_gross: 0.0, realized_pnl_net: 0.0,
+      swap_fees_quote: 0.0, gas_fees_quote: 0.0, unrealized_pnl: 0.0, unrealized_pnl_gross: 0.0, unrealized_pnl_net: 0.0, open_interest_base: 0.0,
+      trade_count: 0, count_won: 0, count_lost: 0, reserves: None }
   }
 }
 
+/// A processed trade with the trader's own position metrics (account_*)
+/// and aggregates across every address in the pool (market_*) as at the trade
+/// account_addr is the transaction sender and trade_addr the trade's buyer on buys or seller on sells
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TradeTx{
   pub tx_hash: String,
+  pub tx_index: u64,
   pub block_num: u64,
   pub block_time: String,
+  pub block_timestamp: i64,
   pub side: Side,
   pub volume_base: f64,
   pub volume_quote: f64,
+  pub notional: f64,
   pub price_quote: f64,
   pub account_addr: String,
+  pub trade_addr: String,
+  pub bot_reasons: Vec<BotReason>,
+  pub wash_reason: Option<WashReason>,
+  pub account_wash_score: f64,
+  pub pool_liquidity: f64,
+  pub pool_price_quote: f64,
+  pub pre_trade_mid_quote: f64,
+  pub slippage: f64,
   pub account_trades_open: usize,
   pub account_won: u64,
   pub account_lost: u64,
   pub account_unrealized_pnl: f64,
   pub account_realized_pnl: f64,
   pub account_external_pnl: f64,
-  pub account_open_interest_base: f64
+  pub account_open_interest_base: f64,
+  pub external_cost_basis: Option<ExternalCostBasis>,
+  pub external_cost_quote: f64,
+  pub gas_used: f64,
+  pub gas_price_gwei: f64,
+  pub fee_tier: f64,
+  pub swap_fee_quote: f64,
+  pub gas_fee_quote: f64,
+  pub account_realized_pnl_gross: f64,
+  pub account_realized_pnl_net: f64,
+  pub account_unrealized_pnl_gross: f64,
+  pub account_unrealized_pnl_net: f64,
+  pub account_cumulative_realized_pnl: f64,
+  pub account_cumulative_external_pnl: f64,
+  pub market_trades_open: usize,
+  pub market_unrealized_pnl: f64,
+  pub market_unrealized_pnl_gross: f64,
+  pub market_unrealized_pnl_net: f64,
+  pub market_open_interest_base: f64
 }
 
 impl TradeTx {
   pub fn new(
-    tx_hash: String, block_num: u64, block_time: String, side: Side, account_addr: String
+    tx_hash: String, block_num: u64, block_timestamp: i64, side: Side, account_addr: String
   ) -> Self { 
-    Self { tx_hash, block_num, block_time, side, volume_base: 0.0, volume_quote: 0.0, price_quote: 0.0, account_addr, account_won: 0, 
+    let block_time: String = format_block_time(block_timestamp);
+    Self { tx_hash, tx_index: 0, block_num, block_time, block_timestamp, side, volume_base: 0.0, volume_quote: 0.0, notional: 0.0, price_quote: 0.0, account_addr, trade_addr: "".to_string(),
+      bot_reasons: vec![], wash_reason: None, account_wash_score: 0.0,
+      pool_liquidity: 0.0, pool_price_quote: 0.0, pre_trade_mid_quote: 0.0, slippage: 0.0, account_won: 0, 
       account_lost: 0, account_trades_open: 0, account_unrealized_pnl: 0.0, account_realized_pnl: 0.0, account_external_pnl: 0.0, 
-      account_open_interest_base: 0.0 }
+      account_open_interest_base: 0.0, external_cost_basis: None, external_cost_quote: 0.0,
+      gas_used: 0.0, gas_price_gwei: 0.0, fee_tier: 0.0, swap_fee_quote: 0.0, gas_fee_quote: 0.0, account_realized_pnl_gross: 0.0,
+      account_realized_pnl_net: 0.0, account_unrealized_pnl_gross: 0.0, account_unrealized_pnl_net: 0.0,
+      account_cumulative_realized_pnl: 0.0, account_cumulative_external_pnl: 0.0, market_trades_open: 0, market_unrealized_pnl: 0.0,
+      market_unrealized_pnl_gross: 0.0, market_unrealized_pnl_net: 0.0, market_open_interest_base: 0.0 }
+  }
+}
+
+#[cfg(test)]
+impl TradeTx {
+  /// Test Trade
+  /// Builds a trade of volume_base at price_quote for tests, in a transaction of its own
+  /// Blocks are a minute apart from 2023-12-06T19:00:00Z, so block 5 trades at 19:05
+  pub fn test_trade(block_num: u64, side: Side, account: &str, volume_base: f64, price_quote: f64) -> Self {
+    let block_timestamp: i64 = 1_701_889_200 + block_num as i64 * 60;
+    let mut trade_tx: TradeTx = TradeTx::new(format!("0x{}", block_num), block_num, block_timestamp, side, account.to_string());
+    trade_tx.volume_base = volume_base;
+    trade_tx.volume_quote = volume_base * price_quote;
+    trade_tx.notional = trade_tx.volume_quote;
+    trade_tx.price_quote = price_quote;
+    trade_tx
+  }
+
+  /// At Index
+  /// Moves a test trade to position tx_index in its block, in a transaction hashed by the index
+  pub fn at_index(mut self, tx_index: u64) -> Self {
+    self.tx_index = tx_index;
+    self.tx_hash = format!("0x{}", tx_index);
+    self
+  }
+
+  /// At Time
+  /// Moves a test trade to block_time
+  pub fn at_time(mut self, block_time: &str) -> Self {
+    self.block_time = block_time.to_string();
+    self.block_timestamp = parse_block_time(block_time).expect("Invalid test block time");
+    self
+  }
+}
+
+/// Parse Block Time
+/// Converts a BitQuery block time (e.g. 2023-12-06T19:54:11Z) into a unix timestamp in seconds
+pub fn parse_block_time(block_time: &str) -> Option<i64> {
+  DateTime::parse_from_rfc3339(block_time).ok().map(|dt| dt.timestamp())
+}
+
+/// Format Block Time
+/// Converts a unix timestamp in seconds back into BitQuery's block time format
+pub fn format_block_time(timestamp: i64) -> String {
+  match Utc.timestamp_opt(timestamp, 0).single() {
+    Some(dt) => dt.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+    None => "".to_string()
+  }
+}
+
+/// OHLCV bar over a fixed clock interval starting at datetime
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct TimeBar {
+  pub datetime: String,
+  pub timestamp: i64,
+  pub open: f64,
+  pub high: f64,
+  pub low: f64,
+  pub close: f64,
+  pub volume_buys: f64,
+  pub volume_sells: f64,
+  pub trade_count: u64,
+  pub unique_traders: u64,
+  pub reserves: Option<ReservePoint>
+}
+
+impl TimeBar {
+  pub fn new() -> Self {
+    Self { datetime: "".to_string(), timestamp: 0, open: 0.0, high: 0.0, low: 0.0, close: 0.0, volume_buys: 0.0, volume_sells: 0.0,
+      trade_count: 0, unique_traders: 0, reserves: None }
+  }
+}
+
+/// OHLCV bar sampled on trade information (tick count, order flow imbalance or runs)
+/// Tick, imbalance and run bars share this shape so they are interchangeable downstream
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct InformationBar {
+  pub datetime: String,
+  pub open: f64,
+  pub high: f64,
+  pub low: f64,
+  pub close: f64,
+  pub volume_base: f64,
+  pub volume_quote: f64,
+  pub notional: f64,
+  pub volume_buys: f64,
+  pub volume_sells: f64,
+  pub trade_count: u64,
+  pub threshold: f64,
+  pub reserves: Option<ReservePoint>
+}
+
+impl InformationBar {
+  pub fn new() -> Self {
+    Self { datetime: "".to_string(), open: 0.0, high: 0.0, low: 0.0, close: 0.0, volume_base: 0.0, volume_quote: 0.0, notional: 0.0,
+      volume_buys: 0.0, volume_sells: 0.0, trade_count: 0, threshold: 0.0, reserves: None }
+  }
+
+  /// Update
+  /// Adds a trade's price and volumes to the bar
+  pub fn update(&mut self, trade_tx: &TradeTx) {
+    let price_quote: f64 = trade_tx.price_quote;
+    if self.datetime.is_empty() {
+      self.datetime = trade_tx.block_time.clone();
+      self.open = price_quote;
+      self.high = price_quote;
+      self.low = price_quote;
+    }
+    if price_quote > self.high { self.high = price_quote; }
+    if price_quote < self.low { self.low = price_quote; }
+    self.close = price_quote;
+    self.volume_base += trade_tx.volume_base;
+    self.volume_quote += trade_tx.volume_quote;
+    self.notional += trade_tx.notional;
+    match trade_tx.side {
+      Side::Buy => self.volume_buys += trade_tx.volume_quote,
+      Side::Sell => self.volume_sells += trade_tx.volume_quote
+    }
+    self.trade_count += 1;
+  }
+}
+
+/// Summary of one wallet's trading in the pool
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct TraderProfile {
+  pub address: String,
+  pub first_block: u64,
+  pub last_block: u64,
+  pub count_buys: u64,
+  pub count_sells: u64,
+  pub quote_in: f64,
+  pub quote_out: f64,
+  pub realized_pnl: f64,
+  pub external_pnl: f64,
+  pub unrealized_pnl: f64,
+  pub win_rate: f64,
+  pub average_holding_secs: f64,
+  pub largest_win: f64,
+  pub largest_loss: f64,
+  pub open_interest_base: f64,
+  pub is_holding: bool,
+  pub is_bot: bool,
+  pub bot_reasons: String,
+  pub wash_score: f64
+}
+
+/// Bot Reason
+/// Why an address was flagged as a bot or sniper
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, Clone, Copy)]
+pub enum BotReason {
+  EarlyBlockBuyer,
+  LiquidityBlockBuyer,
+  MultiTradeBlock,
+  ProxiedTrade
+}
+
+impl BotReason {
+  /// Code
+  /// Short reason code used in reports
+  pub fn code(&self) -> &'static str {
+    match self {
+      BotReason::EarlyBlockBuyer => "EARLY",
+      BotReason::LiquidityBlockBuyer => "LIQ_BLOCK",
+      BotReason::MultiTradeBlock => "MULTI_BLOCK",
+      BotReason::ProxiedTrade => "PROXY"
+    }
   }
+
+  /// Is Bot Evidence
+  /// Whether the reason alone marks an address as a bot
+  /// Proxied trades are reported for context only, as router and aggregator users routinely set another recipient
+  pub fn is_bot_evidence(&self) -> bool {
+    *self != BotReason::ProxiedTrade
+  }
+}
+
+/// A transfer of ETH that funded an address, imported or looked up over RPC
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FundingTransfer {
+  pub from: String,
+  pub to: String,
+  pub block_num: u64,
+  pub amount_eth: f64,
+}
+
+/// Wash Reason
+/// Why a trade was counted as suspected wash volume
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize, Clone, Copy)]
+pub enum WashReason {
+  SelfTrade,
+  FreshWalletPingPong
+}
+
+impl WashReason {
+  /// Code
+  /// Short reason code used in reports
+  pub fn code(&self) -> &'static str {
+    match self {
+      WashReason::SelfTrade => "SELF",
+      WashReason::FreshWalletPingPong => "PING_PONG"
+    }
+  }
+}
+
+/// Cluster Reason
+/// Evidence that linked two addresses into one entity
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize, Clone, Copy)]
+pub enum ClusterReason {
+  SharedFunder,
+  CoTimedTrading,
+  MatchingSizes
+}
+
+impl ClusterReason {
+  /// Code
+  /// Short reason code used in reports
+  pub fn code(&self) -> &'static str {
+    match self {
+      ClusterReason::SharedFunder => "FUNDER",
+      ClusterReason::CoTimedTrading => "CO_TIMED",
+      ClusterReason::MatchingSizes => "SIZES"
+    }
+  }
+}
+
+/// A group of addresses believed to be traded by one operator, identified by its lowest address
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WalletCluster {
+  pub cluster_id: String,
+  pub addresses: Vec<String>,
+  pub funders: Vec<String>,
+  pub reasons: String,
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum MevKind {
+  Sandwich,
+  Arbitrage
+}
+
+/// A sandwich or same-block arbitrage, with profit in quote terms over the matched base quantity
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MevAttack {
+  pub kind: MevKind,
+  pub block_num: u64,
+  pub attacker_addr: String,
+  pub front_run_tx: String,
+  pub back_run_tx: String,
+  pub victim_txs: Vec<String>,
+  pub victim_addrs: Vec<String>,
+  pub matched_base: f64,
+  pub profit_quote: f64
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum LiquidityEventKind {
+  Mint,
+  Burn
+}
+
+/// Liquidity added to or removed from the pool, decoded from a V2 or V3 Mint/Burn log
+/// The provider is the transaction sender, as routers and position managers call the pool on their behalf
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LiquidityEvent {
+  pub block_num: u64,
+  pub block_time: String,
+  pub tx_hash: String,
+  pub tx_index: u64,
+  pub log_index: u64,
+  pub kind: LiquidityEventKind,
+  pub provider_addr: String,
+  pub amount_base: f64,
+  pub amount_quote: f64,
+}
+
+/// Pool reserves and depth as at the end of a block, also attached to each bar as at its last trade
+/// Depth uses the reserves for V2 and the virtual reserves of the active V3 liquidity, with
+/// k their product, liquidity its square root and price impact the move in mid price a 1 quote buy causes
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct ReservePoint {
+  pub block_num: u64,
+  pub datetime: String,
+  pub reserve_base: f64,
+  pub reserve_quote: f64,
+  pub price_quote: f64,
+  pub k: f64,
+  pub liquidity: f64,
+  pub price_impact_per_quote: f64,
 }
 
+/// Raised when the quote burned since the peak quote reserve within the block window reaches
+/// the configured fraction of that peak, drop_fraction being burned quote over the peak
 #[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RugAlert {
+  pub block_num: u64,
+  pub datetime: String,
+  pub tx_hash: String,
+  pub provider_addr: String,
+  pub peak_block_num: u64,
+  pub peak_reserve_quote: f64,
+  pub reserve_quote: f64,
+  pub drop_fraction: f64,
+}
+
+/// Distribution of open pool-acquired holdings across addresses as at a block, with the holders
+/// gained and lost since the previous snapshot
+/// The Gini coefficient and Herfindahl index run from 0 (evenly held) towards 1 (one holder)
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct HolderDistribution {
+  pub block_num: u64,
+  pub datetime: String,
+  pub holder_count: u64,
+  pub total_open_base: f64,
+  pub top_10_share: f64,
+  pub top_50_share: f64,
+  pub gini: f64,
+  pub hhi: f64,
+  pub new_holders: u64,
+  pub exited_holders: u64,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Analysis {
   pub dollar_bars: Option<Vec<DollarBar>>,
+  pub base_volume_bars: Option<Vec<BaseVolumeBar>>,
   pub volume_bars: Option<Vec<VolumeBar>>,
   pub pnl_bars: Option<Vec<PnlBar>>,
+  pub time_bars: Option<Vec<TimeBar>>,
+  pub tick_bars: Option<Vec<InformationBar>>,
+  pub imbalance_bars: Option<Vec<InformationBar>>,
+  pub run_bars: Option<Vec<InformationBar>>,
+  pub trader_profiles: Option<Vec<TraderProfile>>,
+  pub mev_attacks: Option<Vec<MevAttack>>,
+  pub wallet_clusters: Option<Vec<WalletCluster>>,
+  pub entity_profiles: Option<Vec<TraderProfile>>,
+  pub liquidity_events: Option<Vec<LiquidityEvent>>,
+  pub reserve_series: Option<Vec<ReservePoint>>,
+  pub rug_alerts: Option<Vec<RugAlert>>,
+  pub holder_distributions: Option<Vec<HolderDistribution>>,
   pub transactions: Option<Vec<TradeTx>>
 }
 
-impl TimeBars for Analysis {
-  fn new() -> Self {
+impl Analysis {
+  pub fn new() -> Self {
     Self {
-      dollar_bars: None, volume_bars: None, pnl_bars: None, transactions: None
+      dollar_bars: None, base_volume_bars: None, volume_bars: None, pnl_bars: None, time_bars: None, tick_bars: None, imbalance_bars: None,
+      run_bars: None, trader_profiles: None, mev_attacks: None,
+      wallet_clusters: None, entity_profiles: None, liquidity_events: None, reserve_series: None, rug_alerts: None,
+      holder_distributions: None,
+      transactions: None
     }
   }
 }
@@ -105,7 +525,51 @@ impl TimeBars for Analysis {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Criteria {
   pub is_dollar_bars: bool,
+  pub is_base_volume_bars: bool,
   pub is_volume_bars: bool,
   pub is_pnl_bars: bool,
+  pub is_time_bars: bool,
+  pub is_tick_bars: bool,
+  pub is_imbalance_bars: bool,
+  pub is_run_bars: bool,
+  pub is_trader_profiles: bool,
+  pub is_mev_attacks: bool,
+  pub is_wallet_clusters: bool,
+  pub is_liquidity: bool,
+  pub is_holder_distributions: bool,
   pub is_transactions_bars: bool,
 }
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub enum DataSource {
+  BitQuery,
+  SwapLogs
+}
+
+/// External Cost Basis
+/// How tokens sold without a matching buy in the pool are costed when booking external pnl
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize, Clone, Copy)]
+pub enum ExternalCostBasis {
+  #[default]
+  Zero,
+  FirstSeenPrice,
+  SellPrice,
+  Imported
+}
+
+impl std::fmt::Display for ExternalCostBasis {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self {
+      ExternalCostBasis::Zero => write!(f, "Zero"),
+      ExternalCostBasis::FirstSeenPrice => write!(f, "First Seen Price"),
+      ExternalCostBasis::SellPrice => write!(f, "Sell Price"),
+      ExternalCostBasis::Imported => write!(f, "Imported")
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub enum RunMode {
+  Batch,
+  Stream
+}
@@ -1,3 +1,35 @@
-pub trait TimeBars {
-  fn new() -> Self;
+use crate::models::general::TradeTx;
+
+/// Bar Builder
+/// Accumulates trades into a bar and reports when the bar should be sampled
+pub trait BarBuilder {
+  type Bar;
+
+  fn update(&mut self, trade_tx: &TradeTx);
+  fn should_close(&self) -> bool;
+  fn finish(&self) -> Self::Bar;
+  fn reset(&mut self);
+
+  /// Is Empty
+  /// Whether no trades have been added since the last bar closed
+  fn is_empty(&self) -> bool;
+
+  /// Sample
+  /// Updates with a trade and returns the finished bar if it closed, resetting for the next bar
+  fn sample(&mut self, trade_tx: &TradeTx) -> Option<Self::Bar> {
+    self.update(trade_tx);
+    if !self.should_close() { return None }
+    let bar: Self::Bar = self.finish();
+    self.reset();
+    Some(bar)
+  }
+
+  /// Flush
+  /// Returns the partial bar built from the trades since the last bar closed, if any, resetting for the next bar
+  fn flush(&mut self) -> Option<Self::Bar> {
+    if self.is_empty() { return None }
+    let bar: Self::Bar = self.finish();
+    self.reset();
+    Some(bar)
+  }
 }
@@ -0,0 +1,48 @@
+use csv::Reader;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs::File;
+
+#[derive(Debug, Deserialize, Clone)]
+struct QuoteUsdRow {
+  block: u64,
+  price: f64,
+}
+
+/// Quote Usd Series
+/// Quote token (e.g. WETH) to USD prices keyed by block, used to convert quote notional into dollars
+#[derive(Debug, Clone)]
+pub struct QuoteUsdSeries {
+  points: Vec<(u64, f64)>,
+}
+
+impl QuoteUsdSeries {
+  pub fn new(mut points: Vec<(u64, f64)>) -> Self {
+    points.sort_by_key(|(block, _)| *block);
+    Self { points }
+  }
+
+  /// From Csv
+  /// Loads a series from a csv with block and price columns
+  pub fn from_csv(file_path: &str) -> Result<Self, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let mut rdr = Reader::from_reader(file);
+    let mut points: Vec<(u64, f64)> = vec![];
+    for result in rdr.deserialize::<QuoteUsdRow>() {
+      let row: QuoteUsdRow = result?;
+      points.push((row.block, row.price));
+    }
+    Ok(Self::new(points))
+  }
+
+  /// Price At
+  /// Returns the latest price at or before the block, or the earliest price if the block precedes the series
+  pub fn price_at(&self, block_num: u64) -> Option<f64> {
+    let idx: usize = self.points.partition_point(|(block, _)| *block <= block_num);
+    if idx == 0 {
+      self.points.first().map(|(_, price)| *price)
+    } else {
+      Some(self.points[idx - 1].1)
+    }
+  }
+}
@@ -0,0 +1,813 @@
+use crate::models::address::{AddressRecords, CostBasisMethod};
+use crate::models::bitquery::TradeInfo;
+use crate::bars::{BarKind, BarSampler, SampledBars};
+use crate::classifier::{is_bot, BotClassifier};
+use crate::cluster::{self, WalletClusterer};
+use crate::costbasis::ExternalCostBasisResolver;
+use crate::holders::HolderTracker;
+use crate::liquidity::ReserveTracker;
+use crate::mev::MevDetector;
+use crate::models::general::{Analysis, BaseVolumeBar, Criteria, DollarBar, ExternalCostBasis, HolderDistribution, InformationBar, LiquidityEvent, MevAttack, parse_block_time, PnlBar, ReservePoint, RugAlert, Side, TimeBar, TradeTx, VolumeBar};
+use crate::pricefeed::QuoteUsdSeries;
+use crate::WETH;
+use crate::report;
+use crate::wash::WashDetector;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Result of processing one trade
+#[derive(Debug, Clone)]
+pub struct ProcessedTrade {
+  pub trade_tx: TradeTx,
+  pub closed_bars: SampledBars,
+  pub mev_attacks: Vec<MevAttack>,
+  pub holder_distribution: Option<HolderDistribution>,
+}
+
+/// Open Totals
+/// Running sums of every address's open positions, so aggregate unrealized pnl and open interest
+/// are updated by swapping out the trading address's contribution instead of sweeping all addresses
+#[derive(Debug, Default, Clone)]
+pub struct OpenTotals {
+  pub open_qty_base: f64,
+  pub cost_basis_quote: f64,
+  pub swap_fees_quote: f64,
+  pub gas_fees_quote: f64,
+  pub open_positions: i64,
+}
+
+impl OpenTotals {
+  /// Add
+  /// Adds an address's open positions to the totals
+  pub fn add(&mut self, record: &AddressRecords) {
+    self.apply(record, 1.0);
+  }
+
+  /// Remove
+  /// Removes an address's open positions from the totals
+  pub fn remove(&mut self, record: &AddressRecords) {
+    self.apply(record, -1.0);
+  }
+
+  fn apply(&mut self, record: &AddressRecords, sign: f64) {
+    let (swap_fees_quote, gas_fees_quote) = record.get_open_fees();
+    self.open_qty_base += sign * record.get_open_interest();
+    self.cost_basis_quote += sign * record.get_cost_basis();
+    self.swap_fees_quote += sign * swap_fees_quote;
+    self.gas_fees_quote += sign * gas_fees_quote;
+    self.open_positions += sign as i64 * record.count_open_positions() as i64;
+  }
+
+  /// Unrealized Pnl
+  /// Returns aggregate unrealized pnl at the price, p * open qty - cost basis
+  pub fn unrealized_pnl(&self, price_quote: f64) -> f64 {
+    price_quote * self.open_qty_base - self.cost_basis_quote
+  }
+}
+
+/// Trade Processor
+/// Runs the per-trade analytics (side detection, address records, unrealized PnL and bar construction)
+/// one trade at a time so batch runs, streaming and tests share the same engine
+#[derive(Debug)]
+pub struct TradeProcessor {
+  pub pool: String,
+  pub criteria: Criteria,
+  pub bar_sampler: BarSampler,
+  pub quote_usd_series: Option<QuoteUsdSeries>,
+  pub cost_basis_method: CostBasisMethod,
+  pub external_cost_basis: ExternalCostBasisResolver,
+  pub fee_tier: Option<f64>,
+  pub bot_classifier: BotClassifier,
+  pub exclude_bots_from_bars: bool,
+  pub mev_detector: MevDetector,
+  pub wash_detector: WashDetector,
+  pub wallet_clusterer: WalletClusterer,
+  pub reserve_tracker: ReserveTracker,
+  pub holder_tracker: HolderTracker,
+  pub holder_snapshot_bar: Option<BarKind>,
+  pub unique_address_trade_counts_hm: HashMap<String, u64>,
+  pub address_records_hm: HashMap<String, AddressRecords>,
+  pub open_totals: OpenTotals,
+  pub last_price_quote: f64,
+  pub is_native_quote: bool,
+  last_gas_tx_hash: String,
+  dollar_bars: Vec<DollarBar>,
+  base_volume_bars: Vec<BaseVolumeBar>,
+  volume_bars: Vec<VolumeBar>,
+  pnl_bars: Vec<PnlBar>,
+  time_bars: Vec<TimeBar>,
+  tick_bars: Vec<InformationBar>,
+  imbalance_bars: Vec<InformationBar>,
+  run_bars: Vec<InformationBar>,
+  mev_attacks: Vec<MevAttack>,
+  liquidity_events: Vec<LiquidityEvent>,
+  rug_alerts: Vec<RugAlert>,
+  holder_distributions: Vec<HolderDistribution>,
+  transactions: Vec<TradeTx>,
+}
+
+impl TradeProcessor {
+  pub fn new(pool: &str, criteria: Criteria, bar_sampler: BarSampler) -> Self {
+    Self {
+      pool: pool.to_string(),
+      criteria,
+      bar_sampler,
+      quote_usd_series: None,
+      cost_basis_method: CostBasisMethod::Fifo,
+      external_cost_basis: ExternalCostBasisResolver::new(ExternalCostBasis::Zero),
+      fee_tier: None,
+      bot_classifier: BotClassifier::default(),
+      exclude_bots_from_bars: false,
+      mev_detector: MevDetector::new(),
+      wash_detector: WashDetector::default(),
+      wallet_clusterer: WalletClusterer::default(),
+      reserve_tracker: ReserveTracker::default(),
+      holder_tracker: HolderTracker::default(),
+      holder_snapshot_bar: None,
+      unique_address_trade_counts_hm: HashMap::new(),
+      address_records_hm: HashMap::new(),
+      open_totals: OpenTotals::default(),
+      last_price_quote: 0.0,
+      is_native_quote: true,
+      last_gas_tx_hash: "".to_string(),
+      dollar_bars: vec![],
+      base_volume_bars: vec![],
+      volume_bars: vec![],
+      pnl_bars: vec![],
+      time_bars: vec![],
+      tick_bars: vec![],
+      imbalance_bars: vec![],
+      run_bars: vec![],
+      mev_attacks: vec![],
+      liquidity_events: vec![],
+      rug_alerts: vec![],
+      holder_distributions: vec![],
+      transactions: vec![]
+    }
+  }
+
+  /// Set Quote Usd Series
+  /// Converts quote notional to USD before sampling dollar bars
+  pub fn set_quote_usd_series(&mut self, quote_usd_series: QuoteUsdSeries) {
+    self.quote_usd_series = Some(quote_usd_series);
+  }
+
+  /// Set Cost Basis Method
+  /// Chooses which open positions sells are matched against for every address
+  pub fn set_cost_basis_method(&mut self, cost_basis_method: CostBasisMethod) {
+    self.cost_basis_method = cost_basis_method;
+  }
+
+  /// Set External Cost Basis
+  /// Chooses how tokens sold without a matching buy in the pool are costed
+  pub fn set_external_cost_basis(&mut self, external_cost_basis: ExternalCostBasisResolver) {
+    self.external_cost_basis = external_cost_basis;
+  }
+
+  /// Set Fee Tier
+  /// Overrides the pool fee tier (e.g. 0.003 for 0.3%) otherwise inferred from the dex protocol
+  pub fn set_fee_tier(&mut self, fee_tier: f64) {
+    self.fee_tier = Some(fee_tier);
+  }
+
+  /// Set Bot Classifier
+  /// Flags sniper and bot addresses, optionally leaving their trades out of the bars
+  pub fn set_bot_classifier(&mut self, bot_classifier: BotClassifier, exclude_bots_from_bars: bool) {
+    self.bot_classifier = bot_classifier;
+    self.exclude_bots_from_bars = exclude_bots_from_bars;
+  }
+
+  /// Set Wash Detector
+  /// Scores addresses and trades for suspected wash volume
+  pub fn set_wash_detector(&mut self, wash_detector: WashDetector) {
+    self.wash_detector = wash_detector;
+  }
+
+  /// Set Wallet Clusterer
+  /// Groups addresses traded by one operator so pnl can also be reported per entity
+  pub fn set_wallet_clusterer(&mut self, wallet_clusterer: WalletClusterer) {
+    self.wallet_clusterer = wallet_clusterer;
+  }
+
+  /// Set Reserve Tracker
+  /// Tracks pool reserves and raises rug alerts on large liquidity removals
+  pub fn set_reserve_tracker(&mut self, reserve_tracker: ReserveTracker) {
+    self.reserve_tracker = reserve_tracker;
+  }
+
+  /// Set Holder Tracker
+  /// Tracks holder distribution, snapshotting it each time a bar of the given kind closes
+  pub fn set_holder_tracker(&mut self, holder_tracker: HolderTracker, holder_snapshot_bar: BarKind) {
+    self.holder_tracker = holder_tracker;
+    self.holder_snapshot_bar = Some(holder_snapshot_bar);
+  }
+
+  /// Holder Distribution
+  /// Returns the holder distribution as at the last trade without moving the new and exited holder baseline
+  pub fn holder_distribution(&self) -> HolderDistribution {
+    self.holder_tracker.distribution()
+  }
+
+  /// Notional
+  /// Returns the trade's quote volume in USD if a quote/USD series is set, otherwise in quote terms
+  pub fn notional(&self, trade_tx: &TradeTx) -> f64 {
+    match self.quote_usd_series.as_ref().and_then(|series| series.price_at(trade_tx.block_num)) {
+      Some(quote_usd) => trade_tx.volume_quote * quote_usd,
+      None => trade_tx.volume_quote
+    }
+  }
+
+  /// To Trade Tx
+  /// Converts a trade into a transaction with side, volumes and price set
+  /// The pool being the buyer means the trader sold into it
+  /// Gas used comes from the transaction receipt only, as BitQuery's Gas is the gas limit, and is left at 0 when unknown
+  /// Errors when the block time cannot be parsed, as time bars and holding times depend on it
+  pub fn to_trade_tx(&self, trade: TradeInfo) -> Result<TradeTx, Box<dyn Error>> {
+    let block_num: u64 = trade.block.number.parse::<u64>().unwrap();
+    let block_timestamp: i64 = parse_block_time(&trade.block.time)
+      .ok_or_else(|| format!("Unparsable block time {} for transaction {}", trade.block.time, trade.transaction.hash))?;
+    let side: Side = if trade.trade.buyer != self.pool { Side::Buy } else { Side::Sell };
+    let trade_addr: String = if side == Side::Buy { trade.trade.buyer.clone() } else { trade.trade.seller.clone() };
+    let mut trade_tx: TradeTx = TradeTx::new(trade.transaction.hash, block_num, block_timestamp, side, trade.transaction.from);
+
+    let amount_base: f64 = trade.trade.amount.parse::<f64>().expect("Failed to convert String amount to f64");
+    let amount_quote: f64 = trade.trade.side.amount.parse::<f64>().expect("Failed to convert String amount to f64");
+    trade_tx.trade_addr = trade_addr;
+    trade_tx.volume_base = amount_base;
+    trade_tx.volume_quote = amount_quote;
+    trade_tx.price_quote = amount_quote / amount_base;
+    trade_tx.tx_index = trade.transaction.index;
+    trade_tx.fee_tier = self.fee_tier.unwrap_or_else(|| default_fee_tier(&trade.trade.dex.protocol_name));
+    trade_tx.gas_used = trade.transaction.gas_used.and_then(|gas_used| gas_used.parse::<f64>().ok()).unwrap_or(0.0);
+    trade_tx.gas_price_gwei = trade.transaction.gas_price.and_then(|gas_price| gas_price.parse::<f64>().ok()).unwrap_or(0.0) * 1e9;
+    trade_tx.pool_liquidity = trade.trade.pool_liquidity.and_then(|liquidity| liquidity.parse::<f64>().ok()).unwrap_or(0.0);
+    trade_tx.pool_price_quote = trade.trade.pool_price.and_then(|pool_price| pool_price.parse::<f64>().ok()).unwrap_or(0.0);
+    Ok(trade_tx)
+  }
+
+  /// Process Trade
+  /// Converts and processes a single trade, erroring without processing it when it cannot be converted
+  pub fn process_trade(&mut self, trade: TradeInfo) -> Result<ProcessedTrade, Box<dyn Error>> {
+    let is_native_quote: bool = trade.trade.side.currency.smart_contract.eq_ignore_ascii_case(WETH);
+    let trade_tx: TradeTx = self.to_trade_tx(trade)?;
+    self.is_native_quote = is_native_quote;
+    Ok(self.process_trade_tx(trade_tx))
+  }
+
+  /// Process Trade Tx
+  /// Calculates account metrics for a single transaction and updates the open bars
+  /// Gross pnl adds back the pool fee already embedded in execution prices and net pnl deducts gas, which is
+  /// paid in ETH and so only priced in quote when the quote token is WETH; other pools report gas fees and net pnl as NaN
+  /// Buy fees are carried with the position until it closes and sell fees are split between realized and external pnl
+  /// A transaction's gas is charged to its first swap only, as routers and bundles can swap through the pool several times
+  /// Returns the completed transaction and the bars it closed, if any
+  pub fn process_trade_tx(&mut self, mut trade_tx: TradeTx) -> ProcessedTrade {
+    let amount_base: f64 = trade_tx.volume_base;
+    let price_quote: f64 = trade_tx.price_quote;
+    trade_tx.notional = self.notional(&trade_tx);
+    self.last_price_quote = price_quote;
+    trade_tx.swap_fee_quote = trade_tx.volume_quote * trade_tx.fee_tier;
+    trade_tx.gas_fee_quote = if !self.is_native_quote {
+      f64::NAN
+    } else if trade_tx.tx_hash == self.last_gas_tx_hash {
+      0.0
+    } else {
+      trade_tx.gas_used * trade_tx.gas_price_gwei / 1e9
+    };
+    self.last_gas_tx_hash = trade_tx.tx_hash.clone();
+    self.external_cost_basis.observe(price_quote);
+
+    // Classify bots and snipers
+    trade_tx.bot_reasons = self.bot_classifier.observe(&trade_tx);
+
+    // Score self-trading and wash volume
+    trade_tx.wash_reason = self.wash_detector.observe(&trade_tx);
+    trade_tx.account_wash_score = self.wash_detector.score_for(&trade_tx.account_addr);
+
+    // Link wallets by trade timing and size
+    self.wallet_clusterer.observe(&trade_tx);
+
+    // Calculate Count of Trades for Given Address
+    *self.unique_address_trade_counts_hm.entry(trade_tx.account_addr.clone()).or_insert(0) += 1;
+
+    // Update records with current trade and increment realized P&L
+    let mut account_realized_internal_pnl = 0.0;
+    let mut account_realized_external_pnl = 0.0;
+    let cost_basis_method: CostBasisMethod = self.cost_basis_method;
+    let record: &mut AddressRecords = self.address_records_hm.entry(trade_tx.account_addr.clone())
+      .or_insert_with(|| AddressRecords::with_cost_basis(cost_basis_method));
+
+    // Cost only the base sold beyond the account's open positions, which it sourced outside the pool
+    if trade_tx.side == Side::Sell && amount_base > record.get_open_interest() {
+      let (external_cost_basis, external_cost_quote) = self.external_cost_basis.resolve(&trade_tx.account_addr, price_quote);
+      trade_tx.external_cost_basis = Some(external_cost_basis);
+      trade_tx.external_cost_quote = external_cost_quote;
+    }
+    self.open_totals.remove(record);
+    record.record_trade(&trade_tx);
+    let mut account_realized_gross_pnl = 0.0;
+    let mut account_realized_net_pnl = 0.0;
+    if trade_tx.side == Side::Buy {
+      record.open_position_from(&trade_tx);
+    } else {
+      let open_interest_before: f64 = record.get_open_interest();
+      let entry_swap_fees_before: f64 = record.realized_entry_swap_fees_quote;
+      let entry_gas_fees_before: f64 = record.realized_entry_gas_fees_quote;
+      let (internal_pnl, external_pnl) = record.close_positions_from(&trade_tx, trade_tx.external_cost_quote);
+      account_realized_internal_pnl = internal_pnl;
+      account_realized_external_pnl = external_pnl;
+
+      let closed_fraction: f64 = if amount_base > 0.0 { (open_interest_before - record.get_open_interest()) / amount_base } else { 0.0 };
+      let entry_swap_fees: f64 = record.realized_entry_swap_fees_quote - entry_swap_fees_before;
+      let entry_gas_fees: f64 = record.realized_entry_gas_fees_quote - entry_gas_fees_before;
+      account_realized_gross_pnl = internal_pnl + entry_swap_fees + trade_tx.swap_fee_quote * closed_fraction;
+      account_realized_net_pnl = internal_pnl - entry_gas_fees - trade_tx.gas_fee_quote * closed_fraction;
+    }
+    trade_tx.account_won = record.count_profit;
+    trade_tx.account_lost = record.count_loss;
+    trade_tx.account_realized_pnl = account_realized_internal_pnl;
+    trade_tx.account_external_pnl = account_realized_external_pnl;
+    trade_tx.account_realized_pnl_gross = account_realized_gross_pnl;
+    trade_tx.account_realized_pnl_net = account_realized_net_pnl;
+    self.open_totals.add(record);
+
+    // Update the trader's own open position metrics
+    let account_unrealized_pnl: f64 = record.calculate_unrealized_position(price_quote);
+    let (account_open_swap_fees, account_open_gas_fees) = record.get_open_fees();
+    trade_tx.account_trades_open = record.count_open_positions();
+    trade_tx.account_unrealized_pnl = account_unrealized_pnl;
+    trade_tx.account_unrealized_pnl_gross = account_unrealized_pnl + account_open_swap_fees;
+    trade_tx.account_unrealized_pnl_net = account_unrealized_pnl - account_open_gas_fees;
+    trade_tx.account_open_interest_base = record.get_open_interest();
+    trade_tx.account_cumulative_realized_pnl = record.realized_internal_pnl;
+    trade_tx.account_cumulative_external_pnl = record.realized_external_pnl;
+
+    // Update market wide unrealized records from the running totals
+    let market_unrealized_pnl: f64 = self.open_totals.unrealized_pnl(price_quote);
+    trade_tx.market_unrealized_pnl = market_unrealized_pnl;
+    trade_tx.market_unrealized_pnl_gross = market_unrealized_pnl + self.open_totals.swap_fees_quote;
+    trade_tx.market_unrealized_pnl_net = market_unrealized_pnl - self.open_totals.gas_fees_quote;
+    trade_tx.market_open_interest_base = self.open_totals.open_qty_base;
+    trade_tx.market_trades_open = self.open_totals.open_positions.max(0) as usize;
+
+    // Move the swap through the pool reserves, measuring slippage against the pre-trade mid
+    // (execution prices include the pool fee) with positive slippage being a worse price
+    let pre_trade_reserves: Option<ReservePoint> = self.reserve_tracker.current().cloned();
+    if let Some(mid_quote) = self.reserve_tracker.mid_price() {
+      trade_tx.pre_trade_mid_quote = mid_quote;
+      trade_tx.slippage = match trade_tx.side {
+        Side::Buy => price_quote / mid_quote - 1.0,
+        Side::Sell => 1.0 - price_quote / mid_quote
+      };
+    }
+    self.reserve_tracker.apply_trade(&trade_tx);
+
+    // Update bars, leaving out flagged bots when excluded
+    let mut closed_bars: SampledBars = if self.exclude_bots_from_bars && is_bot(&trade_tx.bot_reasons) {
+      SampledBars::default()
+    } else {
+      self.bar_sampler.update(&trade_tx)
+    };
+    closed_bars.set_reserves(self.reserve_tracker.current(), pre_trade_reserves.as_ref());
+
+    // Snapshot holdings when the snapshot bar closes, time bars as at the trade before this one
+    let is_snapshot_bar_closed: bool = self.holder_snapshot_bar.is_some_and(|bar_kind| closed_bars.is_closed(bar_kind));
+    let is_time_snapshot: bool = is_snapshot_bar_closed && self.holder_snapshot_bar == Some(BarKind::Time);
+    let mut holder_distribution: Option<HolderDistribution> = if is_time_snapshot { Some(self.holder_tracker.snapshot()) } else { None };
+    self.holder_tracker.update(&trade_tx);
+    if is_snapshot_bar_closed && !is_time_snapshot { holder_distribution = Some(self.holder_tracker.snapshot()); }
+    if self.criteria.is_holder_distributions { self.holder_distributions.extend(holder_distribution.iter().cloned()); }
+    self.push_closed_bars(&closed_bars);
+
+    // Detect sandwiches and arbitrage once each block is complete
+    let mev_attacks: Vec<MevAttack> = self.mev_detector.observe(&trade_tx);
+    if self.criteria.is_mev_attacks { self.mev_attacks.extend(mev_attacks.iter().cloned()); }
+
+    // Update transactions ledger, which entity pnl is re-run over
+    if self.criteria.is_transactions_bars || self.criteria.is_wallet_clusters { self.transactions.push(trade_tx.clone()); }
+
+    ProcessedTrade { trade_tx, closed_bars, mev_attacks, holder_distribution }
+  }
+
+  /// Process Liquidity Event
+  /// Applies a pool Mint or Burn to the reserves, in order with the trades around it
+  /// Returns a rug alert if the event drained the pool
+  pub fn process_liquidity_event(&mut self, liquidity_event: LiquidityEvent) -> Option<RugAlert> {
+    let rug_alert: Option<RugAlert> = self.reserve_tracker.apply_liquidity(&liquidity_event);
+    if self.criteria.is_liquidity {
+      if let Some(alert) = &rug_alert { self.rug_alerts.push(alert.clone()); }
+      self.liquidity_events.push(liquidity_event);
+    }
+    rug_alert
+  }
+
+  /// Finish
+  /// Closes the partial bars still open at the end of a batch run, keeps them for the snapshot and returns them
+  /// The last block is checked for mev attacks, which are kept for the snapshot
+  pub fn finish(&mut self) -> SampledBars {
+    let mut closed_bars: SampledBars = self.bar_sampler.flush();
+    closed_bars.set_reserves(self.reserve_tracker.current(), self.reserve_tracker.current());
+    if self.criteria.is_holder_distributions && self.holder_snapshot_bar.is_some_and(|bar_kind| closed_bars.is_closed(bar_kind)) {
+      self.holder_distributions.push(self.holder_tracker.snapshot());
+    }
+    self.push_closed_bars(&closed_bars);
+    let mev_attacks: Vec<MevAttack> = self.mev_detector.flush();
+    if self.criteria.is_mev_attacks { self.mev_attacks.extend(mev_attacks); }
+    closed_bars
+  }
+
+  fn push_closed_bars(&mut self, closed_bars: &SampledBars) {
+    if let Some(bar) = &closed_bars.dollar_bar { self.dollar_bars.push(bar.clone()); }
+    if let Some(bar) = &closed_bars.base_volume_bar { self.base_volume_bars.push(bar.clone()); }
+    if let Some(bar) = &closed_bars.volume_bar { self.volume_bars.push(bar.clone()); }
+    if let Some(bar) = &closed_bars.pnl_bar { self.pnl_bars.push(bar.clone()); }
+    self.time_bars.extend(closed_bars.time_bars.iter().cloned());
+    if let Some(bar) = &closed_bars.tick_bar { self.tick_bars.push(bar.clone()); }
+    if let Some(bar) = &closed_bars.imbalance_bar { self.imbalance_bars.push(bar.clone()); }
+    if let Some(bar) = &closed_bars.run_bar { self.run_bars.push(bar.clone()); }
+  }
+
+  /// Snapshot
+  /// Returns an analysis of the bars closed and transactions processed so far, per the processor criteria
+  pub fn snapshot(&self) -> Analysis {
+    let mut analysis: Analysis = Analysis::new();
+    if self.criteria.is_dollar_bars { analysis.dollar_bars = Some(self.dollar_bars.clone()); }
+    if self.criteria.is_base_volume_bars { analysis.base_volume_bars = Some(self.base_volume_bars.clone()); }
+    if self.criteria.is_volume_bars { analysis.volume_bars = Some(self.volume_bars.clone()); }
+    if self.criteria.is_pnl_bars { analysis.pnl_bars = Some(self.pnl_bars.clone()); }
+    if self.criteria.is_time_bars { analysis.time_bars = Some(self.time_bars.clone()); }
+    if self.criteria.is_tick_bars { analysis.tick_bars = Some(self.tick_bars.clone()); }
+    if self.criteria.is_imbalance_bars { analysis.imbalance_bars = Some(self.imbalance_bars.clone()); }
+    if self.criteria.is_run_bars { analysis.run_bars = Some(self.run_bars.clone()); }
+    if self.criteria.is_trader_profiles {
+      analysis.trader_profiles = Some(report::trader_profiles(&self.address_records_hm, self.last_price_quote, &self.bot_classifier, &self.wash_detector));
+    }
+    if self.criteria.is_mev_attacks { analysis.mev_attacks = Some(self.mev_attacks.clone()); }
+    if self.criteria.is_wallet_clusters {
+      let cluster_map: HashMap<String, String> = self.wallet_clusterer.cluster_map();
+      let entity_records_hm: HashMap<String, AddressRecords> = cluster::entity_records(&self.transactions, &cluster_map, self.cost_basis_method, &self.external_cost_basis);
+      analysis.wallet_clusters = Some(self.wallet_clusterer.clusters());
+      analysis.entity_profiles = Some(report::entity_profiles(&entity_records_hm, self.last_price_quote, &cluster_map, &self.bot_classifier, &self.wash_detector));
+    }
+    if self.criteria.is_liquidity {
+      analysis.liquidity_events = Some(self.liquidity_events.clone());
+      analysis.reserve_series = Some(self.reserve_tracker.series().to_vec());
+      analysis.rug_alerts = Some(self.rug_alerts.clone());
+    }
+    if self.criteria.is_holder_distributions { analysis.holder_distributions = Some(self.holder_distributions.clone()); }
+    if self.criteria.is_transactions_bars { analysis.transactions = Some(self.transactions.clone()); }
+    analysis
+  }
+}
+
+/// Default Fee Tier
+/// Uniswap V2 style pools charge a fixed 0.3%, other protocols need the fee tier set explicitly
+fn default_fee_tier(protocol_name: &str) -> f64 {
+  if protocol_name.ends_with("_v2") { 0.003 } else { 0.0 }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::bars::BarTrigger;
+  use crate::models::general::{BotReason, FundingTransfer, LiquidityEventKind, TraderProfile, WalletCluster};
+  use crate::bars::activity::{BaseVolumeBarBuilder, DollarBarBuilder, PnlBarBuilder, VolumeBarBuilder};
+
+  const POOL: &str = "0xpool";
+
+  fn criteria_all() -> Criteria {
+    Criteria { is_dollar_bars: true, is_base_volume_bars: true, is_volume_bars: true, is_pnl_bars: true, is_time_bars: true,
+      is_tick_bars: true, is_imbalance_bars: true, is_run_bars: true, is_trader_profiles: true,
+      is_mev_attacks: true, is_wallet_clusters: true, is_liquidity: true,
+      is_holder_distributions: true, is_transactions_bars: true }
+  }
+
+  fn bar_sampler(notional_limit: f64, base_limit: f64) -> BarSampler {
+    BarSampler {
+      dollar: Some(DollarBarBuilder::new(notional_limit)),
+      base_volume: Some(BaseVolumeBarBuilder::new(base_limit)),
+      volume: Some(VolumeBarBuilder::new(BarTrigger::Notional(notional_limit))),
+      pnl: Some(PnlBarBuilder::new(BarTrigger::TradeCount(1))),
+      time: None,
+      tick: None,
+      imbalance: None,
+      run: None
+    }
+  }
+
+  #[test]
+  fn it_closes_bars_and_snapshots() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), bar_sampler(12.0, 5.0));
+    let first: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 6.0, 1.0));
+    assert!(first.closed_bars.dollar_bar.is_none());
+    assert_eq!(first.closed_bars.base_volume_bar.map(|b| b.volume_base), Some(6.0));
+    assert!(first.closed_bars.pnl_bar.is_some());
+
+    // 6 + 8 quote notional crosses the 12 limit even though only 10 base traded
+    let second: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(2, Side::Sell, "0xa", 4.0, 2.0));
+    assert!(second.closed_bars.base_volume_bar.is_none());
+    let dollar_bar: DollarBar = second.closed_bars.dollar_bar.expect("Expected dollar bar to close");
+    assert_eq!((dollar_bar.open, dollar_bar.high, dollar_bar.close), (1.0, 2.0, 2.0));
+    assert_eq!(dollar_bar.notional, 14.0);
+    let volume_bar: VolumeBar = second.closed_bars.volume_bar.expect("Expected volume bar to close");
+    assert_eq!(volume_bar.volume_buys, 6.0);
+    assert_eq!(volume_bar.volume_sells, 8.0);
+    assert_eq!(second.trade_tx.account_realized_pnl, 4.0);
+    assert_eq!(second.trade_tx.account_open_interest_base, 2.0);
+    assert_eq!(second.trade_tx.market_open_interest_base, 2.0);
+
+    let analysis: Analysis = processor.snapshot();
+    assert_eq!(analysis.dollar_bars.map(|b| b.len()), Some(1));
+    assert_eq!(analysis.pnl_bars.map(|b| b.len()), Some(2));
+    assert_eq!(analysis.transactions.map(|t| t.len()), Some(2));
+    assert_eq!(processor.unique_address_trade_counts_hm["0xa"], 2);
+  }
+
+  #[test]
+  fn it_keeps_partial_bars_on_finish() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), bar_sampler(12.0, 5.0));
+    processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 2.0, 1.0));
+    let closed_bars: SampledBars = processor.finish();
+    assert_eq!(closed_bars.dollar_bar.map(|bar| bar.notional), Some(2.0));
+    assert_eq!(closed_bars.base_volume_bar.map(|bar| bar.volume_base), Some(2.0));
+    assert!(closed_bars.pnl_bar.is_none());
+
+    let analysis: Analysis = processor.snapshot();
+    assert_eq!(analysis.dollar_bars.map(|b| b.len()), Some(1));
+    assert_eq!(analysis.base_volume_bars.map(|b| b.len()), Some(1));
+    assert_eq!(analysis.volume_bars.map(|b| b.len()), Some(1));
+  }
+
+  #[test]
+  fn it_samples_dollar_bars_in_usd() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), bar_sampler(1000.0, 1000.0));
+    processor.set_quote_usd_series(QuoteUsdSeries::new(vec![(1, 2000.0), (3, 2500.0)]));
+    let first: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xa", 100.0, 0.004));
+    assert!(first.closed_bars.dollar_bar.is_none());
+    let second: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(3, Side::Buy, "0xb", 100.0, 0.002));
+    assert_eq!(second.closed_bars.dollar_bar.map(|b| b.notional), Some(1300.0));
+  }
+
+  #[test]
+  fn it_reports_gross_and_net_pnl() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    let mut buy: TradeTx = TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0);
+    buy.fee_tier = 0.01;
+    buy.gas_used = 100_000.0;
+    buy.gas_price_gwei = 2_000.0;
+    let bought: ProcessedTrade = processor.process_trade_tx(buy);
+    assert_eq!((bought.trade_tx.swap_fee_quote, bought.trade_tx.gas_fee_quote), (0.1, 0.2));
+    assert!((bought.trade_tx.account_unrealized_pnl_gross - 0.1).abs() < 1e-12);
+    assert!((bought.trade_tx.account_unrealized_pnl_net + 0.2).abs() < 1e-12);
+
+    // Selling half realizes half the buy fees plus all of the sell fees
+    let mut sell: TradeTx = TradeTx::test_trade(2, Side::Sell, "0xa", 5.0, 2.0);
+    sell.fee_tier = 0.01;
+    sell.gas_used = 100_000.0;
+    sell.gas_price_gwei = 1_000.0;
+    let sold: ProcessedTrade = processor.process_trade_tx(sell);
+    assert_eq!(sold.trade_tx.account_realized_pnl, 5.0);
+    assert!((sold.trade_tx.account_realized_pnl_gross - 5.15).abs() < 1e-12);
+    assert!((sold.trade_tx.account_realized_pnl_net - 4.8).abs() < 1e-12);
+  }
+
+  #[test]
+  fn it_charges_gas_once_per_transaction() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    let mut first_leg: TradeTx = TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0);
+    first_leg.gas_used = 100_000.0;
+    first_leg.gas_price_gwei = 2_000.0;
+    let mut second_leg: TradeTx = first_leg.clone();
+    second_leg.volume_base = 5.0;
+    second_leg.volume_quote = 5.0;
+    assert_eq!(processor.process_trade_tx(first_leg).trade_tx.gas_fee_quote, 0.2);
+    let second: ProcessedTrade = processor.process_trade_tx(second_leg);
+    assert_eq!(second.trade_tx.gas_fee_quote, 0.0);
+    assert!((second.trade_tx.account_unrealized_pnl_net + 0.2).abs() < 1e-12);
+  }
+
+  #[test]
+  fn it_resolves_external_cost_basis_only_for_external_sells() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    processor.set_external_cost_basis(ExternalCostBasisResolver::new(ExternalCostBasis::SellPrice));
+    let bought: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0));
+    assert_eq!((bought.trade_tx.external_cost_basis, bought.trade_tx.external_cost_quote), (None, 0.0));
+    let sold: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(2, Side::Sell, "0xa", 5.0, 2.0));
+    assert_eq!((sold.trade_tx.external_cost_basis, sold.trade_tx.external_cost_quote), (None, 0.0));
+    // 5 of these 8 close the remaining buy and 3 were sourced outside the pool
+    let oversold: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(3, Side::Sell, "0xa", 8.0, 3.0));
+    assert_eq!((oversold.trade_tx.external_cost_basis, oversold.trade_tx.external_cost_quote), (Some(ExternalCostBasis::SellPrice), 3.0));
+    assert_eq!(oversold.trade_tx.account_realized_pnl, 10.0);
+  }
+
+  #[test]
+  fn it_matches_running_totals_to_full_sweep() {
+    let accounts: [&str; 4] = ["0xa", "0xb", "0xc", "0xd"];
+    for cost_basis_method in [CostBasisMethod::Fifo, CostBasisMethod::Lifo, CostBasisMethod::AverageCost, CostBasisMethod::Hifo] {
+      let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+      processor.set_cost_basis_method(cost_basis_method);
+      for block_num in 1..200u64 {
+        let account: &str = accounts[(block_num * 7 % 4) as usize];
+        let side: Side = if block_num % 3 == 0 { Side::Sell } else { Side::Buy };
+        let volume_base: f64 = 1.0 + (block_num * 13 % 17) as f64;
+        let price_quote: f64 = 1.0 + (block_num * 31 % 23) as f64 / 10.0;
+        let mut tx: TradeTx = TradeTx::test_trade(block_num, side, account, volume_base, price_quote);
+        tx.fee_tier = 0.003;
+        tx.gas_used = 100_000.0;
+        tx.gas_price_gwei = 20.0;
+        let processed: ProcessedTrade = processor.process_trade_tx(tx);
+
+        let mut unrealized_pnl: f64 = 0.0;
+        let mut open_interest_base: f64 = 0.0;
+        let mut unrealized_net_pnl: f64 = 0.0;
+        let mut open_positions: usize = 0;
+        for record in processor.address_records_hm.values() {
+          let (_, open_gas_fees) = record.get_open_fees();
+          unrealized_pnl += record.calculate_unrealized_position(price_quote);
+          unrealized_net_pnl += record.calculate_unrealized_position(price_quote) - open_gas_fees;
+          open_interest_base += record.get_open_interest();
+          open_positions += record.count_open_positions();
+        }
+        assert!((processed.trade_tx.market_unrealized_pnl - unrealized_pnl).abs() < 1e-6, "{:?} block {}", cost_basis_method, block_num);
+        assert!((processed.trade_tx.market_unrealized_pnl_net - unrealized_net_pnl).abs() < 1e-6);
+        assert!((processed.trade_tx.market_open_interest_base - open_interest_base).abs() < 1e-6);
+        assert_eq!(processed.trade_tx.market_trades_open, open_positions);
+      }
+    }
+  }
+
+  #[test]
+  fn it_separates_account_and_market_metrics() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0));
+    processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xa", 10.0, 1.0));
+    processor.process_trade_tx(TradeTx::test_trade(3, Side::Buy, "0xb", 5.0, 2.0));
+    processor.process_trade_tx(TradeTx::test_trade(4, Side::Sell, "0xa", 5.0, 2.0));
+    let processed: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(5, Side::Sell, "0xa", 10.0, 3.0));
+
+    let trade_tx: TradeTx = processed.trade_tx;
+    assert_eq!(trade_tx.account_trades_open, 1);
+    assert_eq!(trade_tx.account_open_interest_base, 5.0);
+    assert_eq!(trade_tx.account_unrealized_pnl, 10.0);
+    assert_eq!(trade_tx.account_cumulative_realized_pnl, 25.0);
+    assert_eq!(trade_tx.market_trades_open, 2);
+    assert_eq!(trade_tx.market_open_interest_base, 10.0);
+    assert_eq!(trade_tx.market_unrealized_pnl, 15.0);
+  }
+
+  #[test]
+  fn it_excludes_bots_from_bars() {
+    let mut sampler: BarSampler = BarSampler::new();
+    sampler.dollar = Some(DollarBarBuilder::new(1.0));
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), sampler);
+    let mut bot_classifier: BotClassifier = BotClassifier::new(1, u64::MAX);
+    bot_classifier.launch_block = Some(1);
+    processor.set_bot_classifier(bot_classifier, true);
+
+    let sniped: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0));
+    assert_eq!(sniped.trade_tx.bot_reasons, vec![BotReason::EarlyBlockBuyer]);
+    assert!(sniped.closed_bars.dollar_bar.is_none());
+    // The sniper stays flagged when it later sells
+    assert!(processor.process_trade_tx(TradeTx::test_trade(5, Side::Sell, "0xa", 10.0, 1.0)).closed_bars.dollar_bar.is_none());
+    assert!(processor.process_trade_tx(TradeTx::test_trade(5, Side::Buy, "0xb", 10.0, 1.0)).closed_bars.dollar_bar.is_some());
+
+    let analysis: Analysis = processor.snapshot();
+    let profiles: Vec<TraderProfile> = analysis.trader_profiles.expect("Expected trader profiles");
+    assert_eq!((profiles[0].is_bot, profiles[0].bot_reasons.as_str()), (true, "EARLY"));
+    assert!(!profiles[1].is_bot);
+  }
+
+  #[test]
+  fn it_tracks_reserves_through_trades_and_liquidity() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    processor.set_reserve_tracker(ReserveTracker::new(0.5, 5));
+    let liquidity_event = |block_num: u64, kind: LiquidityEventKind, amount_base: f64, amount_quote: f64| LiquidityEvent {
+      block_num, block_time: format!("t{}", block_num), tx_hash: "0xlp".to_string(), tx_index: 0, log_index: 0, kind,
+      provider_addr: "0xdeployer".to_string(), amount_base, amount_quote
+    };
+    assert!(processor.process_liquidity_event(liquidity_event(1, LiquidityEventKind::Mint, 100.0, 10.0)).is_none());
+    processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xa", 10.0, 0.2));
+    assert!(processor.process_liquidity_event(liquidity_event(3, LiquidityEventKind::Burn, 90.0, 12.0)).is_some());
+
+    let analysis: Analysis = processor.snapshot();
+    let reserves: Vec<(f64, f64)> = analysis.reserve_series.expect("Expected reserve series").iter()
+      .map(|point| (point.reserve_base, point.reserve_quote))
+      .collect();
+    assert_eq!(reserves, vec![(100.0, 10.0), (90.0, 12.0), (0.0, 0.0)]);
+    assert_eq!(analysis.rug_alerts.map(|alerts| alerts.len()), Some(1));
+    assert_eq!(analysis.liquidity_events.map(|events| events.len()), Some(2));
+  }
+
+  #[test]
+  fn it_attaches_reserves_and_slippage() {
+    let mut sampler: BarSampler = BarSampler::new();
+    sampler.dollar = Some(DollarBarBuilder::new(1.0));
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), sampler);
+    processor.process_liquidity_event(LiquidityEvent {
+      block_num: 1, block_time: "t1".to_string(), tx_hash: "0xlp".to_string(), tx_index: 0, log_index: 0,
+      kind: LiquidityEventKind::Mint, provider_addr: "0xdeployer".to_string(), amount_base: 100.0, amount_quote: 10.0
+    });
+
+    let processed: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xa", 10.0, 0.2));
+    assert_eq!(processed.trade_tx.pre_trade_mid_quote, 0.1);
+    assert!((processed.trade_tx.slippage - 1.0).abs() < 1e-9);
+    let reserves: ReservePoint = processed.closed_bars.dollar_bar.and_then(|bar| bar.reserves).expect("Expected bar reserves");
+    assert_eq!((reserves.block_num, reserves.reserve_base, reserves.reserve_quote), (2, 90.0, 12.0));
+  }
+
+  #[test]
+  fn it_snapshots_holder_distribution_at_bar_close() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), bar_sampler(1000.0, 1000.0));
+    processor.set_holder_tracker(HolderTracker::new(0.001), BarKind::Pnl);
+    processor.process_trade_tx(TradeTx::test_trade(1, Side::Buy, "0xa", 30.0, 1.0));
+    let processed: ProcessedTrade = processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xb", 10.0, 1.0));
+    let distribution: HolderDistribution = processed.holder_distribution.expect("Expected holder distribution");
+    assert_eq!((distribution.block_num, distribution.holder_count, distribution.new_holders), (2, 2, 1));
+    assert_eq!(distribution.top_10_share, 1.0);
+
+    processor.process_trade_tx(TradeTx::test_trade(3, Side::Sell, "0xa", 30.0, 1.0));
+    assert_eq!(processor.holder_distribution().exited_holders, 0);
+    let distributions: Vec<HolderDistribution> = processor.snapshot().holder_distributions.expect("Expected holder distributions");
+    let exited: Vec<u64> = distributions.iter().map(|distribution| distribution.exited_holders).collect();
+    assert_eq!(exited, vec![0, 0, 1]);
+    assert_eq!(distributions[2].holder_count, 1);
+  }
+
+  #[test]
+  fn it_reports_entity_level_pnl() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    let mut wallet_clusterer: WalletClusterer = WalletClusterer::default();
+    wallet_clusterer.add_funding_transfers(&[
+      FundingTransfer { from: "0xf".to_string(), to: "0xa".to_string(), block_num: 1, amount_eth: 1.0 },
+      FundingTransfer { from: "0xf".to_string(), to: "0xb".to_string(), block_num: 1, amount_eth: 1.0 }
+    ]);
+    processor.set_wallet_clusterer(wallet_clusterer);
+    processor.process_trade_tx(TradeTx::test_trade(2, Side::Buy, "0xa", 10.0, 1.0));
+    processor.process_trade_tx(TradeTx::test_trade(3, Side::Sell, "0xb", 10.0, 2.0));
+    processor.process_trade_tx(TradeTx::test_trade(4, Side::Buy, "0xc", 5.0, 2.0));
+
+    let analysis: Analysis = processor.snapshot();
+    let clusters: Vec<WalletCluster> = analysis.wallet_clusters.expect("Expected wallet clusters");
+    assert_eq!(clusters[0].addresses, vec!["0xa".to_string(), "0xb".to_string()]);
+    let entities: Vec<TraderProfile> = analysis.entity_profiles.expect("Expected entity profiles");
+    let addresses: Vec<&str> = entities.iter().map(|profile| profile.address.as_str()).collect();
+    assert_eq!(addresses, vec!["0xa", "0xc"]);
+    // 0xb's sell closes 0xa's buy instead of being booked as an external gain
+    assert_eq!((entities[0].realized_pnl, entities[0].external_pnl), (10.0, 0.0));
+    assert!(!entities[0].is_holding);
+  }
+
+  #[test]
+  fn it_leaves_net_pnl_unpriced_outside_weth_pools() {
+    let mut processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    let trade_json: String = format!(r#"{{
+      "Block": {{ "Number": "5", "Time": "2023-12-06T19:05:00Z" }},
+      "ChainId": "1",
+      "Trade": {{
+        "Amount": "100", "Buyer": "0xa", "Seller": "{}", "Price": 0.0,
+        "Currency": {{ "SmartContract": "0xtoken", "Symbol": "SYNC" }},
+        "Dex": {{ "ProtocolName": "uniswap_v2" }},
+        "Side": {{ "Amount": "200", "Currency": {{ "SmartContract": "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", "Symbol": "USDC" }} }}
+      }},
+      "Transaction": {{ "Hash": "0x5", "From": "0xa", "GasPrice": "0.00000003", "GasUsed": "120000" }}
+    }}"#, POOL);
+    let trade: TradeInfo = serde_json::from_str::<TradeInfo>(&trade_json).expect("Failed to parse trade");
+    let bought: ProcessedTrade = processor.process_trade(trade).expect("Failed to process trade");
+    assert!(bought.trade_tx.gas_fee_quote.is_nan());
+    assert!(bought.trade_tx.account_unrealized_pnl_net.is_nan());
+    assert!((bought.trade_tx.account_unrealized_pnl_gross - 0.6).abs() < 1e-9);
+  }
+
+  #[test]
+  fn it_detects_side_from_pool_buyer() {
+    let processor: TradeProcessor = TradeProcessor::new(POOL, criteria_all(), BarSampler::new());
+    let trade_json: String = format!(r#"{{
+      "Block": {{ "Number": "5", "Time": "2023-12-06T19:05:00Z" }},
+      "ChainId": "1",
+      "Trade": {{
+        "Amount": "100", "Buyer": "{}", "Seller": "0xa", "Price": 0.0,
+        "Currency": {{ "SmartContract": "0xtoken", "Symbol": "SYNC" }},
+        "Dex": {{ "ProtocolName": "uniswap_v2" }},
+        "Side": {{ "Amount": "2", "Currency": {{ "SmartContract": "0xweth", "Symbol": "WETH" }} }}
+      }},
+      "Transaction": {{ "Hash": "0x5", "From": "0xa", "Gas": "200000", "GasPrice": "0.00000003", "GasUsed": "120000" }}
+    }}"#, POOL);
+    let trade: TradeInfo = serde_json::from_str::<TradeInfo>(&trade_json).expect("Failed to parse trade");
+    let trade_tx: TradeTx = processor.to_trade_tx(trade).expect("Failed to convert trade");
+    assert_eq!(trade_tx.side, Side::Sell);
+    assert_eq!(trade_tx.price_quote, 0.02);
+    assert_eq!(trade_tx.trade_addr, "0xa");
+    assert_eq!(trade_tx.fee_tier, 0.003);
+    // The gas limit is not the gas used
+    assert_eq!(trade_tx.gas_used, 120000.0);
+    assert!((trade_tx.gas_price_gwei - 30.0).abs() < 1e-9);
+    let mut limit_only: TradeInfo = serde_json::from_str::<TradeInfo>(&trade_json).expect("Failed to parse trade");
+    limit_only.transaction.gas_used = None;
+    assert_eq!(processor.to_trade_tx(limit_only).expect("Failed to convert trade").gas_used, 0.0);
+
+    // A trade without a usable block time is refused rather than placed at the epoch
+    let mut untimed: TradeInfo = serde_json::from_str::<TradeInfo>(&trade_json).expect("Failed to parse trade");
+    untimed.block.time = "t5".to_string();
+    assert!(processor.to_trade_tx(untimed).is_err());
+  }
+}
@@ -0,0 +1,160 @@
+use crate::classifier::{is_bot, reason_codes, BotClassifier};
+use crate::models::address::AddressRecords;
+use crate::models::general::{BotReason, TraderProfile};
+use crate::wash::WashDetector;
+use csv::Writer;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+
+/// Trader Profile
+/// Summarizes an address's records, valuing its open positions at the price and tagging any bot reasons and wash score
+pub fn trader_profile(address: &str, record: &AddressRecords, price_quote: f64, bot_reasons: &[BotReason], wash_score: f64) -> TraderProfile {
+  let open_interest_base: f64 = record.get_open_interest();
+  TraderProfile {
+    address: address.to_string(),
+    first_block: record.first_block,
+    last_block: record.last_block,
+    count_buys: record.count_buys,
+    count_sells: record.count_sells,
+    quote_in: record.quote_in,
+    quote_out: record.quote_out,
+    realized_pnl: record.realized_internal_pnl,
+    external_pnl: record.realized_external_pnl,
+    unrealized_pnl: record.calculate_unrealized_position(price_quote),
+    win_rate: record.win_rate(),
+    average_holding_secs: record.average_holding_secs(),
+    largest_win: record.largest_win,
+    largest_loss: record.largest_loss,
+    open_interest_base,
+    is_holding: open_interest_base > 0.0,
+    is_bot: is_bot(bot_reasons),
+    bot_reasons: reason_codes(bot_reasons),
+    wash_score
+  }
+}
+
+/// Trader Profiles
+/// Builds a profile for every address, ordered by address
+pub fn trader_profiles(
+  address_records_hm: &HashMap<String, AddressRecords>, price_quote: f64, bot_classifier: &BotClassifier, wash_detector: &WashDetector
+) -> Vec<TraderProfile> {
+  let mut profiles: Vec<TraderProfile> = address_records_hm.iter()
+    .map(|(address, record)| trader_profile(address, record, price_quote, &bot_classifier.reasons_for(address), wash_detector.score_for(address)))
+    .collect();
+  profiles.sort_by(|a, b| a.address.cmp(&b.address));
+  profiles
+}
+
+/// Entity Profiles
+/// Builds a profile for every cluster from its entity level records, tagged with every bot reason
+/// of its member addresses and their combined wash score
+pub fn entity_profiles(
+  entity_records_hm: &HashMap<String, AddressRecords>, price_quote: f64, cluster_map: &HashMap<String, String>,
+  bot_classifier: &BotClassifier, wash_detector: &WashDetector
+) -> Vec<TraderProfile> {
+  let mut members: HashMap<&String, Vec<&String>> = HashMap::new();
+  for (address, cluster_id) in cluster_map.iter() {
+    members.entry(cluster_id).or_default().push(address);
+  }
+  let mut profiles: Vec<TraderProfile> = entity_records_hm.iter()
+    .map(|(cluster_id, record)| {
+      let cluster_members: &[&String] = members.get(cluster_id).map(|members| members.as_slice()).unwrap_or_default();
+      let bot_reasons: BTreeSet<BotReason> = cluster_members.iter().flat_map(|address| bot_classifier.reasons_for(address)).collect();
+      let (trade_count, wash_trade_count) = cluster_members.iter()
+        .filter_map(|address| wash_detector.scores.get(*address))
+        .fold((0, 0), |(trades, wash_trades), score| (trades + score.trade_count, wash_trades + score.wash_trade_count));
+      let wash_score: f64 = if trade_count > 0 { wash_trade_count as f64 / trade_count as f64 } else { 0.0 };
+      trader_profile(cluster_id, record, price_quote, &bot_reasons.into_iter().collect::<Vec<BotReason>>(), wash_score)
+    })
+    .collect();
+  profiles.sort_by(|a, b| a.address.cmp(&b.address));
+  profiles
+}
+
+/// Sort Trader Profiles
+/// Sorts profiles by any column, named as in the JSON and CSV output
+pub fn sort_trader_profiles(profiles: &mut [TraderProfile], column: &str, descending: bool) -> Result<(), Box<dyn Error>> {
+  let mut keyed: Vec<(Value, TraderProfile)> = vec![];
+  for profile in profiles.iter() {
+    let value: Value = serde_json::to_value(profile)?;
+    let key: Value = value.get(column).cloned().ok_or(format!("Unknown trader profile column: {}", column))?;
+    keyed.push((key, profile.clone()));
+  }
+  keyed.sort_by(|(a, _), (b, _)| {
+    let ordering: Ordering = compare_values(a, b);
+    if descending { ordering.reverse() } else { ordering }
+  });
+  for (slot, (_, profile)) in profiles.iter_mut().zip(keyed) {
+    *slot = profile;
+  }
+  Ok(())
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+  match (a, b) {
+    (Value::Number(a), Value::Number(b)) => a.as_f64().unwrap_or(0.0).total_cmp(&b.as_f64().unwrap_or(0.0)),
+    (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+    (Value::String(a), Value::String(b)) => a.cmp(b),
+    _ => Ordering::Equal
+  }
+}
+
+/// Save Trader Profiles Csv
+/// Writes one row per address
+pub fn save_trader_profiles_csv(file_path: &str, profiles: &[TraderProfile]) -> Result<(), Box<dyn Error>> {
+  let file = File::create(file_path)?;
+  let mut wtr = Writer::from_writer(file);
+  for profile in profiles {
+    wtr.serialize(profile)?;
+  }
+  wtr.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::models::general::{Side, TradeTx};
+
+  #[test]
+  fn it_profiles_a_trader() {
+    let mut record: AddressRecords = AddressRecords::new();
+    for (trade_tx, is_buy) in [
+      (TradeTx::test_trade(1, Side::Buy, "0xa", 10.0, 1.0), true),
+      (TradeTx::test_trade(2, Side::Buy, "0xa", 10.0, 3.0), true),
+      (TradeTx::test_trade(4, Side::Sell, "0xa", 15.0, 2.0), false)
+    ] {
+      record.record_trade(&trade_tx);
+      if is_buy { record.open_position_from(&trade_tx); } else { record.close_positions_from(&trade_tx, 0.0); }
+    }
+
+    let profile: TraderProfile = trader_profile("0xa", &record, 4.0, &[], 0.0);
+    assert_eq!((profile.first_block, profile.last_block), (1, 4));
+    assert_eq!((profile.count_buys, profile.count_sells), (2, 1));
+    assert_eq!((profile.quote_in, profile.quote_out), (40.0, 30.0));
+    assert_eq!(profile.realized_pnl, 5.0);
+    assert_eq!(profile.unrealized_pnl, 5.0);
+    // One closing sell, netting 5 across a winning and a losing lot
+    assert_eq!(profile.win_rate, 1.0);
+    assert_eq!((profile.largest_win, profile.largest_loss), (5.0, 0.0));
+    // 10 held 3 minutes and 5 held 2 minutes
+    assert_eq!(profile.average_holding_secs, (10.0 * 180.0 + 5.0 * 120.0) / 15.0);
+    assert!(profile.is_holding);
+  }
+
+  #[test]
+  fn it_sorts_by_any_column() {
+    let mut profiles: Vec<TraderProfile> = ["0xa", "0xb", "0xc"].iter().zip([2.0, -1.0, 5.0])
+      .map(|(address, realized_pnl)| TraderProfile { address: address.to_string(), realized_pnl, ..TraderProfile::default() })
+      .collect();
+    sort_trader_profiles(&mut profiles, "realized_pnl", true).expect("Failed to sort");
+    let addresses: Vec<&str> = profiles.iter().map(|p| p.address.as_str()).collect();
+    assert_eq!(addresses, vec!["0xc", "0xa", "0xb"]);
+    sort_trader_profiles(&mut profiles, "address", false).expect("Failed to sort");
+    assert_eq!(profiles[0].address, "0xa");
+    assert!(sort_trader_profiles(&mut profiles, "missing", false).is_err());
+  }
+}
@@ -0,0 +1,107 @@
+use crate::models::bitquery::{TradeInfo, Transaction};
+use crate::models::general::FundingTransfer;
+use ethers::prelude::{Middleware, Provider, Http};
+use ethers::types::{Address, BlockId, BlockNumber, H256, U256};
+use ethers::utils::format_units;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::str::FromStr;
+
+/// Gas a transaction used and the effective price it paid per unit, from its receipt
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionGas {
+  pub gas_used: U256,
+  pub effective_gas_price: U256,
+}
+
+impl TransactionGas {
+  /// Apply To
+  /// Sets the gas used and effective gas price on a trade's transaction in BitQuery's units (gas price in ETH per gas)
+  pub fn apply_to(&self, transaction: &mut Transaction) {
+    transaction.gas_used = Some(self.gas_used.to_string());
+    transaction.gas_price = format_units(self.effective_gas_price, "ether").ok();
+  }
+}
+
+/// Get Transaction Gas
+/// Looks up the transaction receipt for its gas used and effective gas price
+pub async fn get_transaction_gas<M: Middleware>(provider: &M, tx_hash: H256) -> Result<TransactionGas, Box<dyn Error>> where M::Error: 'static {
+  let receipt = provider.get_transaction_receipt(tx_hash).await?.ok_or("Transaction receipt not found")?;
+  Ok(TransactionGas {
+    gas_used: receipt.gas_used.unwrap_or_default(),
+    effective_gas_price: receipt.effective_gas_price.unwrap_or_default()
+  })
+}
+
+/// Fill Transaction Gas
+/// Looks up receipts for trades whose source did not include the gas used, once per transaction
+pub async fn fill_transaction_gas(rpc_url: &str, trades: &mut [TradeInfo]) -> Result<(), Box<dyn Error>> {
+  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let mut tx_gas: HashMap<String, TransactionGas> = HashMap::new();
+  for trade in trades.iter_mut().filter(|t| t.transaction.gas_used.is_none()) {
+    let gas: TransactionGas = match tx_gas.entry(trade.transaction.hash.clone()) {
+      Entry::Occupied(entry) => *entry.get(),
+      Entry::Vacant(entry) => {
+        let tx_hash: H256 = H256::from_str(&trade.transaction.hash)?;
+        *entry.insert(get_transaction_gas(&provider, tx_hash).await?)
+      }
+    };
+    gas.apply_to(&mut trade.transaction);
+  }
+  Ok(())
+}
+
+/// Get Funding Parent
+/// Finds the first block the address held ETH in before before_block and returns the transfer into it
+/// from that block's transactions
+/// An address's balance only falls through its own transactions, so it is non-decreasing up to the block of
+/// its first one, found by binary searching the nonce; the first funded block is binary searched below that,
+/// even when a burner wallet has spent everything by before_block
+/// Needs an archive node, and funding through internal calls (e.g. contract withdrawals) is not found
+pub async fn get_funding_parent<M: Middleware>(provider: &M, address: Address, before_block: u64) -> Result<Option<FundingTransfer>, Box<dyn Error>> where M::Error: 'static {
+  let block_id = |block_num: u64| Some(BlockId::Number(BlockNumber::Number(block_num.into())));
+  let balance_at = |block_num: u64| provider.get_balance(address, block_id(block_num));
+  let nonce_at = |block_num: u64| provider.get_transaction_count(address, block_id(block_num));
+
+  let mut high: u64 = before_block;
+  if !nonce_at(before_block).await?.is_zero() {
+    let mut low: u64 = 0;
+    while low < high {
+      let mid: u64 = low + (high - low) / 2;
+      if nonce_at(mid).await?.is_zero() { low = mid + 1 } else { high = mid }
+    }
+    // Funded in an earlier block, or in the first transaction's own block if nothing was held before it
+    if high > 0 && !balance_at(high - 1).await?.is_zero() { high -= 1 }
+  } else if balance_at(before_block).await?.is_zero() {
+    return Ok(None)
+  }
+  let mut low: u64 = 0;
+  while low < high {
+    let mid: u64 = low + (high - low) / 2;
+    if balance_at(mid).await?.is_zero() { low = mid + 1 } else { high = mid }
+  }
+
+  let block = provider.get_block_with_txs(low).await?.ok_or("Funding block not found")?;
+  let funding_tx = block.transactions.iter().find(|tx| tx.to == Some(address) && !tx.value.is_zero());
+  Ok(funding_tx.map(|tx| FundingTransfer {
+    from: format!("{:?}", tx.from),
+    to: format!("{:?}", address),
+    block_num: low,
+    amount_eth: format_units(tx.value, "ether").ok().and_then(|amount| amount.parse::<f64>().ok()).unwrap_or(0.0)
+  }))
+}
+
+/// Get Funding Transfers
+/// Looks up the funding parent of each address before the block it first traded in
+pub async fn get_funding_transfers(rpc_url: &str, first_blocks: &HashMap<String, u64>) -> Result<Vec<FundingTransfer>, Box<dyn Error>> {
+  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let mut transfers: Vec<FundingTransfer> = vec![];
+  for (address, first_block) in first_blocks {
+    let address: Address = Address::from_str(address)?;
+    if let Some(transfer) = get_funding_parent(&provider, address, first_block.saturating_sub(1)).await? {
+      transfers.push(transfer);
+    }
+  }
+  Ok(transfers)
+}
@@ -0,0 +1,77 @@
+use crate::models::bitquery::TradeInfo;
+use crate::models::general::LiquidityEvent;
+use crate::rpcalls::{get_transaction_gas, TransactionGas};
+use crate::swaplogs::{
+  decode_liquidity_log, decode_swap_log, get_block_time, get_pool_reserves_at, into_liquidity_event, into_trade_info,
+  v2_burn_topic, v2_mint_topic, v2_swap_topic, v3_burn_topic, v3_mint_topic, v3_swap_topic, PoolInfo
+};
+use ethers::prelude::{Middleware, Provider, StreamExt, Ws};
+use ethers::types::Filter;
+use std::error::Error;
+
+/// Pool Event
+/// A live swap or liquidity add/removal, in the order the pool emitted them, after the pool's reserves
+/// (block number, base and quote) at the block streaming starts from
+#[derive(Debug, Clone)]
+pub enum PoolEvent {
+  Reserves(u64, f64, f64),
+  Trade(Box<TradeInfo>),
+  Liquidity(LiquidityEvent)
+}
+
+/// Stream Pool Events
+/// Subscribes to new V2/V3 Swap, Mint and Burn logs for the pool and hands each decoded event to on_event as it arrives
+/// The pool's reserves at the head block are read once subscribed and handed over first, so live reserves
+/// start from the pool's balances, and logs up to that block are skipped as the balances already include them
+/// Runs until the subscription closes
+pub async fn stream_pool_events<F: FnMut(PoolEvent)>(ws_url: &str, pool: &str, token: &str, mut on_event: F) -> Result<(), Box<dyn Error>> {
+  let provider = Provider::<Ws>::connect(ws_url).await?;
+  let pool_info: PoolInfo = PoolInfo::load(&provider, pool, token).await?;
+  let filter: Filter = Filter::new()
+    .address(pool_info.pool)
+    .topic0(vec![v2_swap_topic(), v3_swap_topic(), v2_mint_topic(), v2_burn_topic(), v3_mint_topic(), v3_burn_topic()]);
+
+  let mut stream = provider.subscribe_logs(&filter).await?;
+  let seed_block: u64 = provider.get_block_number().await?.as_u64();
+  let (reserve_base, reserve_quote) = get_pool_reserves_at(&provider, &pool_info, seed_block).await?;
+  on_event(PoolEvent::Reserves(seed_block, reserve_base, reserve_quote));
+  println!("streaming swaps and liquidity for pool {} from block {}...", pool, seed_block);
+
+  // Events arrive block by block so only the current block's time needs caching
+  let mut block_time_cache: Option<(u64, String)> = None;
+  while let Some(log) = stream.next().await {
+    // Events already processed cannot be unwound, so reorged logs are reported and skipped
+    if log.removed == Some(true) {
+      println!("skipping removed log from reorg: {:?}", log.transaction_hash);
+      continue;
+    }
+    let swap = decode_swap_log(&log, &pool_info);
+    let liquidity = if swap.is_none() { decode_liquidity_log(&log, &pool_info) } else { None };
+    let Some(block_num) = swap.as_ref().map(|s| s.block_num).or(liquidity.as_ref().map(|l| l.block_num)) else { continue };
+    if block_num <= seed_block { continue; }
+
+    let block_time: String = match &block_time_cache {
+      Some((cached_block, cached_time)) if *cached_block == block_num => cached_time.clone(),
+      _ => {
+        let fetched_time: String = get_block_time(&provider, block_num).await?;
+        block_time_cache = Some((block_num, fetched_time.clone()));
+        fetched_time
+      }
+    };
+
+    if let Some(swap) = swap {
+      let tx = provider.get_transaction(swap.tx_hash).await?.ok_or("Transaction not found")?;
+      let tx_gas: TransactionGas = get_transaction_gas(&provider, swap.tx_hash).await?;
+      if let Some(mut trade) = into_trade_info(&swap, &pool_info, &block_time, tx.from) {
+        tx_gas.apply_to(&mut trade.transaction);
+        on_event(PoolEvent::Trade(Box::new(trade)));
+      }
+    } else if let Some(liquidity) = liquidity {
+      let tx = provider.get_transaction(liquidity.tx_hash).await?.ok_or("Transaction not found")?;
+      if let Some(liquidity_event) = into_liquidity_event(&liquidity, &pool_info, &block_time, tx.from) {
+        on_event(PoolEvent::Liquidity(liquidity_event));
+      }
+    }
+  }
+  Err("Pool log subscription closed".into())
+}
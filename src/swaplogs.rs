@@ -0,0 +1,537 @@
+use crate::models::bitquery::{BlockInfo, Currency, Dex, LogInfo, Side, Trade, TradeInfo, Transaction};
+use crate::models::general::{LiquidityEvent, LiquidityEventKind};
+use crate::rpcalls::{get_transaction_gas, TransactionGas};
+use ethers::abi::{decode, encode, ParamType, Token};
+use ethers::prelude::{Middleware, Provider, Http};
+use ethers::types::{Address, BlockId, BlockNumber, Bytes, Filter, Log, TransactionRequest, H256, I256, U256};
+use ethers::utils::{format_units, keccak256};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::error::Error;
+use std::str::FromStr;
+
+/// Blocks requested per eth_getLogs call (kept under common provider range caps)
+const LOG_BLOCK_CHUNK: u64 = 2000;
+
+/// Uniswap V2 Swap(address indexed sender, uint amount0In, uint amount1In, uint amount0Out, uint amount1Out, address indexed to)
+pub fn v2_swap_topic() -> H256 {
+  H256::from(keccak256("Swap(address,uint256,uint256,uint256,uint256,address)"))
+}
+
+/// Uniswap V3 Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)
+pub fn v3_swap_topic() -> H256 {
+  H256::from(keccak256("Swap(address,address,int256,int256,uint160,uint128,int24)"))
+}
+
+/// Uniswap V2 Mint(address indexed sender, uint amount0, uint amount1)
+pub fn v2_mint_topic() -> H256 {
+  H256::from(keccak256("Mint(address,uint256,uint256)"))
+}
+
+/// Uniswap V2 Burn(address indexed sender, uint amount0, uint amount1, address indexed to)
+pub fn v2_burn_topic() -> H256 {
+  H256::from(keccak256("Burn(address,uint256,uint256,address)"))
+}
+
+/// Uniswap V3 Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+/// The position manager's IncreaseLiquidity is always paired with this pool event for the same amounts
+pub fn v3_mint_topic() -> H256 {
+  H256::from(keccak256("Mint(address,address,int24,int24,uint128,uint256,uint256)"))
+}
+
+/// Uniswap V3 Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+/// The position manager's DecreaseLiquidity is always paired with this pool event for the same amounts
+pub fn v3_burn_topic() -> H256 {
+  H256::from(keccak256("Burn(address,int24,int24,uint128,uint256,uint256)"))
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolInfo {
+  pub pool: Address,
+  pub base: Currency,
+  pub quote: Currency,
+  pub base_decimals: u32,
+  pub quote_decimals: u32,
+  pub base_is_token0: bool,
+  pub chain_id: u64,
+}
+
+impl PoolInfo {
+  /// Load
+  /// Looks up the pool's token0/token1 and the decimals and symbol of each side
+  /// The base token is the token being analysed, the quote token is the other side of the pair
+  /// The chain id is read from the provider so trades are tagged with the network they came from
+  pub async fn load<M: Middleware>(provider: &M, pool: &str, token: &str) -> Result<Self, Box<dyn Error>> where M::Error: 'static {
+    let pool_addr: Address = Address::from_str(pool)?;
+    let token_addr: Address = Address::from_str(token)?;
+    let token0: Address = call_address(provider, pool_addr, "token0()").await?;
+    let token1: Address = call_address(provider, pool_addr, "token1()").await?;
+    let base_is_token0: bool = token0 == token_addr;
+    if !base_is_token0 && token1 != token_addr { return Err("Token is not part of the pool".into()) }
+    let quote_addr: Address = if base_is_token0 { token1 } else { token0 };
+
+    Ok(Self {
+      pool: pool_addr,
+      base: Currency { smart_contract: format!("{:?}", token_addr), symbol: call_symbol(provider, token_addr).await },
+      quote: Currency { smart_contract: format!("{:?}", quote_addr), symbol: call_symbol(provider, quote_addr).await },
+      base_decimals: call_u32(provider, token_addr, "decimals()").await?,
+      quote_decimals: call_u32(provider, quote_addr, "decimals()").await?,
+      base_is_token0,
+      chain_id: provider.get_chainid().await?.as_u64()
+    })
+  }
+}
+
+/// A swap decoded from a pool log, before block time and transaction sender are known
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedSwap {
+  pub block_num: u64,
+  pub tx_hash: H256,
+  pub tx_index: u64,
+  pub log_index: u64,
+  pub protocol_name: String,
+  pub is_buy: bool,
+  pub trader: Option<Address>,
+  pub amount_base: U256,
+  pub amount_quote: U256,
+  pub sqrt_price_x96: Option<U256>,
+  pub liquidity: Option<U256>,
+}
+
+/// Decode Swap Log
+/// Decodes a Uniswap V2 or V3 Swap log into base/quote amounts and side
+/// The trader is the recipient on buys; on sells the log's sender is the router, so the trader is left
+/// for into_trade_info to resolve from the transaction sender
+/// Returns None for logs that are not swaps or are still pending
+pub fn decode_swap_log(log: &Log, pool_info: &PoolInfo) -> Option<DecodedSwap> {
+  let topic0: H256 = *log.topics.first()?;
+  let block_num: u64 = log.block_number?.as_u64();
+  let tx_hash: H256 = log.transaction_hash?;
+  let tx_index: u64 = log.transaction_index.map(|i| i.as_u64()).unwrap_or(0);
+  let log_index: u64 = log.log_index.map(|i| i.as_u64()).unwrap_or(0);
+  let recipient: Address = Address::from(*log.topics.get(2)?);
+
+  let mut pool_state: (Option<U256>, Option<U256>) = (None, None);
+  let (protocol_name, is_buy, amount_base, amount_quote) = if topic0 == v2_swap_topic() {
+    let tokens: Vec<Token> = decode(&vec![ParamType::Uint(256); 4], &log.data).ok()?;
+    let amounts: Vec<U256> = tokens.into_iter().filter_map(|t| t.into_uint()).collect();
+    let (amount0_in, amount1_in, amount0_out, amount1_out) = (amounts[0], amounts[1], amounts[2], amounts[3]);
+    let (base_in, base_out, quote_in, quote_out) = if pool_info.base_is_token0 {
+      (amount0_in, amount0_out, amount1_in, amount1_out)
+    } else {
+      (amount1_in, amount1_out, amount0_in, amount0_out)
+    };
+
+    // The pool paying out base means the trader bought base
+    let is_buy: bool = base_out > base_in;
+    if is_buy {
+      ("uniswap_v2", true, base_out - base_in, quote_in.saturating_sub(quote_out))
+    } else {
+      ("uniswap_v2", false, base_in - base_out, quote_out.saturating_sub(quote_in))
+    }
+  } else if topic0 == v3_swap_topic() {
+    let params: [ParamType; 5] = [ParamType::Int(256), ParamType::Int(256), ParamType::Uint(160), ParamType::Uint(128), ParamType::Int(24)];
+    let tokens: Vec<Token> = decode(&params, &log.data).ok()?;
+    let amount0: I256 = I256::from_raw(tokens[0].clone().into_int()?);
+    let amount1: I256 = I256::from_raw(tokens[1].clone().into_int()?);
+    let (amount_base, amount_quote) = if pool_info.base_is_token0 { (amount0, amount1) } else { (amount1, amount0) };
+    pool_state = (tokens[2].clone().into_uint(), tokens[3].clone().into_uint());
+
+    // Amounts are signed from the pool's perspective, negative base means base left the pool
+    ("uniswap_v3", amount_base.is_negative(), amount_base.unsigned_abs(), amount_quote.unsigned_abs())
+  } else {
+    return None
+  };
+
+  Some(DecodedSwap {
+    block_num,
+    tx_hash,
+    tx_index,
+    log_index,
+    protocol_name: protocol_name.to_string(),
+    is_buy,
+    trader: if is_buy { Some(recipient) } else { None },
+    amount_base,
+    amount_quote,
+    sqrt_price_x96: pool_state.0,
+    liquidity: pool_state.1
+  })
+}
+
+/// V3 Pool State
+/// Converts a swap's sqrtPriceX96 and active liquidity into the post-swap price in quote per base
+/// and liquidity in sqrt(base * quote) units, both adjusted for token decimals
+pub fn v3_pool_state(sqrt_price_x96: U256, liquidity: U256, pool_info: &PoolInfo) -> Option<(f64, f64)> {
+  let sqrt_price: f64 = sqrt_price_x96.to_string().parse::<f64>().ok()? / 2f64.powi(96);
+  let (token0_decimals, token1_decimals) = if pool_info.base_is_token0 {
+    (pool_info.base_decimals, pool_info.quote_decimals)
+  } else {
+    (pool_info.quote_decimals, pool_info.base_decimals)
+  };
+  let token1_per_token0: f64 = sqrt_price * sqrt_price * 10f64.powi(token0_decimals as i32 - token1_decimals as i32);
+  if token1_per_token0 <= 0.0 { return None }
+  let price_quote: f64 = if pool_info.base_is_token0 { token1_per_token0 } else { 1.0 / token1_per_token0 };
+  let liquidity: f64 = liquidity.to_string().parse::<f64>().ok()? / 10f64.powf((token0_decimals + token1_decimals) as f64 / 2.0);
+  Some((price_quote, liquidity))
+}
+
+/// Into Trade Info
+/// Shapes a decoded swap like a BitQuery DEXTradeByTokens row so it can feed the same pipeline
+/// As with BitQuery, the buyer is the pool on sells and the receiving address on buys, and sellers are the transaction sender
+pub fn into_trade_info(swap: &DecodedSwap, pool_info: &PoolInfo, block_time: &str, tx_from: Address) -> Option<TradeInfo> {
+  let amount_base: String = format_units(swap.amount_base, pool_info.base_decimals).ok()?;
+  let amount_quote: String = format_units(swap.amount_quote, pool_info.quote_decimals).ok()?;
+  let base_f64: f64 = amount_base.parse::<f64>().ok()?;
+  let quote_f64: f64 = amount_quote.parse::<f64>().ok()?;
+  let pool_str: String = format!("{:?}", pool_info.pool);
+  let trader_str: String = format!("{:?}", swap.trader.unwrap_or(tx_from));
+  let (buyer, seller) = if swap.is_buy { (trader_str, pool_str) } else { (pool_str, trader_str) };
+  let pool_state: Option<(f64, f64)> = match (swap.sqrt_price_x96, swap.liquidity) {
+    (Some(sqrt_price_x96), Some(liquidity)) => v3_pool_state(sqrt_price_x96, liquidity, pool_info),
+    _ => None
+  };
+
+  Some(TradeInfo {
+    block: BlockInfo { number: swap.block_num.to_string(), time: block_time.to_string() },
+    chain_id: pool_info.chain_id.to_string(),
+    trade: Trade {
+      amount: amount_base,
+      buyer,
+      currency: pool_info.base.clone(),
+      dex: Dex { protocol_name: swap.protocol_name.clone() },
+      price: if base_f64 > 0.0 { quote_f64 / base_f64 } else { 0.0 },
+      seller,
+      side: Side { amount: amount_quote, currency: pool_info.quote.clone() },
+      pool_liquidity: pool_state.map(|(_, liquidity)| liquidity.to_string()),
+      pool_price: pool_state.map(|(price_quote, _)| price_quote.to_string())
+    },
+    transaction: Transaction {
+      hash: format!("{:?}", swap.tx_hash),
+      from: format!("{:?}", tx_from),
+      index: swap.tx_index,
+      gas: None,
+      gas_price: None,
+      gas_used: None
+    },
+    log: Some(LogInfo { index: swap.log_index })
+  })
+}
+
+/// A liquidity add or removal decoded from a pool log, before block time and transaction sender are known
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLiquidity {
+  pub block_num: u64,
+  pub tx_hash: H256,
+  pub tx_index: u64,
+  pub log_index: u64,
+  pub kind: LiquidityEventKind,
+  pub amount_base: U256,
+  pub amount_quote: U256,
+}
+
+/// Decode Liquidity Log
+/// Decodes a Uniswap V2 or V3 Mint/Burn log into base/quote amounts
+/// Returns None for logs that are not liquidity events or are still pending
+pub fn decode_liquidity_log(log: &Log, pool_info: &PoolInfo) -> Option<DecodedLiquidity> {
+  let topic0: H256 = *log.topics.first()?;
+  let (kind, params) = if topic0 == v2_mint_topic() {
+    (LiquidityEventKind::Mint, vec![ParamType::Uint(256), ParamType::Uint(256)])
+  } else if topic0 == v2_burn_topic() {
+    (LiquidityEventKind::Burn, vec![ParamType::Uint(256), ParamType::Uint(256)])
+  } else if topic0 == v3_mint_topic() {
+    (LiquidityEventKind::Mint, vec![ParamType::Address, ParamType::Uint(128), ParamType::Uint(256), ParamType::Uint(256)])
+  } else if topic0 == v3_burn_topic() {
+    (LiquidityEventKind::Burn, vec![ParamType::Uint(128), ParamType::Uint(256), ParamType::Uint(256)])
+  } else {
+    return None
+  };
+
+  // amount0 and amount1 are always the last two words
+  let tokens: Vec<Token> = decode(&params, &log.data).ok()?;
+  let amount0: U256 = tokens[tokens.len() - 2].clone().into_uint()?;
+  let amount1: U256 = tokens[tokens.len() - 1].clone().into_uint()?;
+  let (amount_base, amount_quote) = if pool_info.base_is_token0 { (amount0, amount1) } else { (amount1, amount0) };
+
+  Some(DecodedLiquidity {
+    block_num: log.block_number?.as_u64(),
+    tx_hash: log.transaction_hash?,
+    tx_index: log.transaction_index.map(|i| i.as_u64()).unwrap_or(0),
+    log_index: log.log_index.map(|i| i.as_u64()).unwrap_or(0),
+    kind,
+    amount_base,
+    amount_quote
+  })
+}
+
+/// Into Liquidity Event
+/// Converts a decoded liquidity log into token amounts attributed to the transaction sender
+pub fn into_liquidity_event(liquidity: &DecodedLiquidity, pool_info: &PoolInfo, block_time: &str, tx_from: Address) -> Option<LiquidityEvent> {
+  Some(LiquidityEvent {
+    block_num: liquidity.block_num,
+    block_time: block_time.to_string(),
+    tx_hash: format!("{:?}", liquidity.tx_hash),
+    tx_index: liquidity.tx_index,
+    log_index: liquidity.log_index,
+    kind: liquidity.kind,
+    provider_addr: format!("{:?}", tx_from),
+    amount_base: format_units(liquidity.amount_base, pool_info.base_decimals).ok()?.parse::<f64>().ok()?,
+    amount_quote: format_units(liquidity.amount_quote, pool_info.quote_decimals).ok()?.parse::<f64>().ok()?
+  })
+}
+
+/// Get Pool Logs
+/// Pulls the pool's logs with any of the given topics over a block range in chunks
+pub async fn get_pool_logs(provider: &Provider<Http>, pool: Address, topics: Vec<H256>, from_block: u64, till_block: u64) -> Result<Vec<Log>, Box<dyn Error>> {
+  let mut logs: Vec<Log> = vec![];
+  let mut chunk_start: u64 = from_block;
+  while chunk_start <= till_block {
+    let chunk_end: u64 = (chunk_start + LOG_BLOCK_CHUNK - 1).min(till_block);
+    let filter: Filter = Filter::new()
+      .address(pool)
+      .topic0(topics.clone())
+      .from_block(chunk_start)
+      .to_block(chunk_end);
+    let chunk_logs: Vec<Log> = provider.get_logs(&filter).await?;
+    println!("fetched {} pool logs for blocks {} to {}", chunk_logs.len(), chunk_start, chunk_end);
+    logs.extend(chunk_logs);
+    chunk_start = chunk_end + 1;
+  }
+  Ok(logs)
+}
+
+/// Get Swap Logs
+/// Pulls V2 and V3 Swap logs for the pool over a block range in chunks
+pub async fn get_swap_logs(provider: &Provider<Http>, pool: Address, from_block: u64, till_block: u64) -> Result<Vec<Log>, Box<dyn Error>> {
+  get_pool_logs(provider, pool, vec![v2_swap_topic(), v3_swap_topic()], from_block, till_block).await
+}
+
+/// Get Liquidity Events
+/// Decodes all V2 and V3 Mint/Burn events for the pool between from_block and till_block (capped at the chain head)
+/// Returns events in block then log order
+pub async fn get_liquidity_events(rpc_url: &str, pool: &str, token: &str, from_block: u64, till_block: u64) -> Result<Vec<LiquidityEvent>, Box<dyn Error>> {
+  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let pool_info: PoolInfo = PoolInfo::load(&provider, pool, token).await?;
+  let head_block: u64 = provider.get_block_number().await?.as_u64();
+  let topics: Vec<H256> = vec![v2_mint_topic(), v2_burn_topic(), v3_mint_topic(), v3_burn_topic()];
+  let logs: Vec<Log> = get_pool_logs(&provider, pool_info.pool, topics, from_block, till_block.min(head_block)).await?;
+
+  let mut decoded: Vec<DecodedLiquidity> = logs.iter().filter_map(|log| decode_liquidity_log(log, &pool_info)).collect();
+  decoded.sort_by_key(|l| (l.block_num, l.log_index));
+
+  let mut block_times: HashMap<u64, String> = HashMap::new();
+  let mut events: Vec<LiquidityEvent> = vec![];
+  for liquidity in decoded {
+    if let Entry::Vacant(entry) = block_times.entry(liquidity.block_num) {
+      entry.insert(get_block_time(&provider, liquidity.block_num).await?);
+    }
+    let tx = provider.get_transaction(liquidity.tx_hash).await?.ok_or("Transaction not found")?;
+    if let Some(event) = into_liquidity_event(&liquidity, &pool_info, &block_times[&liquidity.block_num], tx.from) {
+      events.push(event);
+    }
+  }
+  Ok(events)
+}
+
+/// Get Swap Trades
+/// Decodes all swaps for the pool between from_block and till_block (capped at the chain head)
+/// Returns trades in block then log order
+pub async fn get_swap_trades(rpc_url: &str, pool: &str, token: &str, from_block: u64, till_block: u64) -> Result<Vec<TradeInfo>, Box<dyn Error>> {
+  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let pool_info: PoolInfo = PoolInfo::load(&provider, pool, token).await?;
+  let head_block: u64 = provider.get_block_number().await?.as_u64();
+  let logs: Vec<Log> = get_swap_logs(&provider, pool_info.pool, from_block, till_block.min(head_block)).await?;
+
+  let mut swaps: Vec<DecodedSwap> = logs.iter().filter_map(|log| decode_swap_log(log, &pool_info)).collect();
+  swaps.sort_by_key(|s| (s.block_num, s.log_index));
+
+  let mut block_times: HashMap<u64, String> = HashMap::new();
+  let mut tx_details: HashMap<H256, (Address, TransactionGas)> = HashMap::new();
+  let mut trades: Vec<TradeInfo> = vec![];
+  for swap in swaps {
+    if let Entry::Vacant(entry) = block_times.entry(swap.block_num) {
+      entry.insert(get_block_time(&provider, swap.block_num).await?);
+    }
+    if let Entry::Vacant(entry) = tx_details.entry(swap.tx_hash) {
+      let tx = provider.get_transaction(swap.tx_hash).await?.ok_or("Transaction not found")?;
+      entry.insert((tx.from, get_transaction_gas(&provider, swap.tx_hash).await?));
+    }
+    let (tx_from, tx_gas) = tx_details[&swap.tx_hash];
+    if let Some(mut trade) = into_trade_info(&swap, &pool_info, &block_times[&swap.block_num], tx_from) {
+      tx_gas.apply_to(&mut trade.transaction);
+      trades.push(trade);
+    }
+  }
+  Ok(trades)
+}
+
+/// Get Pool Reserves
+/// Returns the pool's base and quote token balances at the end of block_num, which are the V2 reserves
+/// once synced and the real (not virtual) reserves of a V3 pool
+pub async fn get_pool_reserves(rpc_url: &str, pool: &str, token: &str, block_num: u64) -> Result<(f64, f64), Box<dyn Error>> {
+  let provider = Provider::<Http>::try_from(rpc_url)?;
+  let pool_info: PoolInfo = PoolInfo::load(&provider, pool, token).await?;
+  get_pool_reserves_at(&provider, &pool_info, block_num).await
+}
+
+/// Get Pool Reserves At
+/// Returns the loaded pool's base and quote token balances at the end of block_num over an existing provider
+pub async fn get_pool_reserves_at<M: Middleware>(provider: &M, pool_info: &PoolInfo, block_num: u64) -> Result<(f64, f64), Box<dyn Error>> where M::Error: 'static {
+  let base_addr: Address = Address::from_str(&pool_info.base.smart_contract)?;
+  let quote_addr: Address = Address::from_str(&pool_info.quote.smart_contract)?;
+  let reserve_base: U256 = call_balance_of(provider, base_addr, pool_info.pool, block_num).await?;
+  let reserve_quote: U256 = call_balance_of(provider, quote_addr, pool_info.pool, block_num).await?;
+  Ok((
+    format_units(reserve_base, pool_info.base_decimals)?.parse::<f64>()?,
+    format_units(reserve_quote, pool_info.quote_decimals)?.parse::<f64>()?
+  ))
+}
+
+/// Get Block Time
+/// Returns the block timestamp in the same format BitQuery uses (e.g. 2023-12-06T19:54:11Z)
+pub async fn get_block_time<M: Middleware>(provider: &M, block_num: u64) -> Result<String, Box<dyn Error>> where M::Error: 'static {
+  let block = provider.get_block(block_num).await?.ok_or("Block not found")?;
+  Ok(block.time()?.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// Calls a view function taking no arguments
+async fn call_view<M: Middleware>(provider: &M, contract: Address, signature: &str) -> Result<Bytes, Box<dyn Error>> where M::Error: 'static {
+  let selector: [u8; 32] = keccak256(signature);
+  let tx = TransactionRequest::new().to(contract).data(selector[..4].to_vec());
+  Ok(provider.call(&tx.into(), None).await?)
+}
+
+async fn call_address<M: Middleware>(provider: &M, contract: Address, signature: &str) -> Result<Address, Box<dyn Error>> where M::Error: 'static {
+  let output: Bytes = call_view(provider, contract, signature).await?;
+  let tokens: Vec<Token> = decode(&[ParamType::Address], &output)?;
+  tokens.into_iter().next().and_then(|t| t.into_address()).ok_or("Failed to decode address".into())
+}
+
+async fn call_u32<M: Middleware>(provider: &M, contract: Address, signature: &str) -> Result<u32, Box<dyn Error>> where M::Error: 'static {
+  let output: Bytes = call_view(provider, contract, signature).await?;
+  let tokens: Vec<Token> = decode(&[ParamType::Uint(8)], &output)?;
+  tokens.into_iter().next().and_then(|t| t.into_uint()).map(|u| u.as_u32()).ok_or("Failed to decode uint".into())
+}
+
+async fn call_balance_of<M: Middleware>(provider: &M, contract: Address, owner: Address, block_num: u64) -> Result<U256, Box<dyn Error>> where M::Error: 'static {
+  let selector: [u8; 32] = keccak256("balanceOf(address)");
+  let data: Vec<u8> = [&selector[..4], &encode(&[Token::Address(owner)])[..]].concat();
+  let tx = TransactionRequest::new().to(contract).data(data);
+  let output: Bytes = provider.call(&tx.into(), Some(BlockId::Number(BlockNumber::Number(block_num.into())))).await?;
+  let tokens: Vec<Token> = decode(&[ParamType::Uint(256)], &output)?;
+  tokens.into_iter().next().and_then(|t| t.into_uint()).ok_or("Failed to decode uint".into())
+}
+
+/// Symbols are informational only, so tokens with non-standard symbol() return an empty string
+async fn call_symbol<M: Middleware>(provider: &M, contract: Address) -> String where M::Error: 'static {
+  let Ok(output) = call_view(provider, contract, "symbol()").await else { return "".to_string() };
+  match decode(&[ParamType::String], &output) {
+    Ok(tokens) => tokens.into_iter().next().and_then(|t| t.into_string()).unwrap_or_default(),
+    Err(_) => "".to_string()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{POOL, TOKEN};
+
+  fn pool_fixture(base_is_token0: bool) -> PoolInfo {
+    PoolInfo {
+      pool: Address::from_str(POOL).unwrap(),
+      base: Currency { smart_contract: TOKEN.to_string(), symbol: "SYNC".to_string() },
+      quote: Currency { smart_contract: "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".to_string(), symbol: "WETH".to_string() },
+      base_decimals: 18,
+      quote_decimals: 18,
+      base_is_token0,
+      chain_id: 1
+    }
+  }
+
+  fn word(value: &str) -> String {
+    format!("{:0>64}", value)
+  }
+
+  fn log_fixture(topic0: H256, data: String) -> Log {
+    let log_json: String = format!(r#"{{
+      "address": "{}",
+      "topics": ["{:?}", "0x0000000000000000000000007a250d5630b4cf539739df2c5dacb4c659f2488d", "0x000000000000000000000000c836145c8e0bd22e755017fb8fd71ad91bb3c834"],
+      "data": "0x{}",
+      "blockHash": "0x{}",
+      "blockNumber": "0x11dca0d",
+      "transactionHash": "0xf72605c6179d2f150972f8f4a4856b37172b31dd5ff0dc5c5e7a4c8624ef9712",
+      "transactionIndex": "0x5",
+      "logIndex": "0x9",
+      "removed": false
+    }}"#, POOL, topic0, data, word("1"));
+    serde_json::from_str::<Log>(&log_json).expect("Failed to parse log fixture")
+  }
+
+  #[test]
+  fn it_decodes_v2_buy() {
+    // token0 = SYNC, token1 = WETH: 0.2 WETH in, 1294934 SYNC out
+    let data: String = format!("{}{}{}{}", word("0"), word("2c68af0bb140000"), word("112368346f1a003980000"), word("0"));
+    let swap: DecodedSwap = decode_swap_log(&log_fixture(v2_swap_topic(), data), &pool_fixture(true)).expect("Failed to decode swap");
+    assert!(swap.is_buy);
+    assert_eq!(swap.block_num, 18729485);
+    assert_eq!(swap.tx_index, 5);
+    assert_eq!(format!("{:?}", swap.trader), "Some(0xc836145c8e0bd22e755017fb8fd71ad91bb3c834)");
+
+    let trade: TradeInfo = into_trade_info(&swap, &pool_fixture(true), "2023-12-06T19:54:23Z", Address::zero()).expect("Failed to shape trade");
+    assert_eq!(trade.trade.buyer, "0xc836145c8e0bd22e755017fb8fd71ad91bb3c834");
+    assert_eq!(trade.chain_id, "1");
+    assert_eq!(trade.trade.side.amount.parse::<f64>().unwrap(), 0.2);
+    assert_eq!(trade.trade.amount.parse::<f64>().unwrap(), 1294934.0);
+    assert_eq!(trade.trade.seller, POOL);
+    assert_ne!(trade.trade.buyer, POOL);
+  }
+
+  #[test]
+  fn it_decodes_v3_sell() {
+    // token0 = WETH, token1 = SYNC: pool receives 1000 SYNC (positive) and pays 0.05 WETH (negative)
+    let weth_out: String = format!("{:x}", I256::from(-50_000_000_000_000_000i64).into_raw());
+    let sync_in: String = word("3635c9adc5dea00000");
+    let data: String = format!("{}{}{}{}{}", weth_out, sync_in, word("1"), word("1"), word("0"));
+    let pool_info: PoolInfo = pool_fixture(false);
+    let swap: DecodedSwap = decode_swap_log(&log_fixture(v3_swap_topic(), data), &pool_info).expect("Failed to decode swap");
+    assert!(!swap.is_buy);
+    assert_eq!(swap.protocol_name, "uniswap_v3");
+    // The log's sender is the router, so the seller comes from the transaction instead
+    assert_eq!(swap.trader, None);
+
+    let tx_from: Address = Address::from_str("0xc836145c8e0bd22e755017fb8fd71ad91bb3c834").unwrap();
+    let trade: TradeInfo = into_trade_info(&swap, &pool_info, "2023-12-06T19:54:23Z", tx_from).expect("Failed to shape trade");
+    assert_eq!(trade.trade.seller, "0xc836145c8e0bd22e755017fb8fd71ad91bb3c834");
+    assert_eq!(trade.trade.amount.parse::<f64>().unwrap(), 1000.0);
+    assert_eq!(trade.trade.side.amount.parse::<f64>().unwrap(), 0.05);
+    assert_eq!(trade.trade.buyer, POOL);
+  }
+
+  #[test]
+  fn it_converts_v3_pool_state() {
+    // token0 = WETH, token1 = SYNC at sqrtPriceX96 = 2^97, so 4 SYNC per WETH
+    let (price_quote, liquidity) = v3_pool_state(U256::from(2).pow(U256::from(97)), U256::exp10(18), &pool_fixture(false)).expect("Failed to convert");
+    assert_eq!(price_quote, 0.25);
+    assert_eq!(liquidity, 1.0);
+  }
+
+  #[test]
+  fn it_decodes_v2_burn() {
+    // token0 = SYNC, token1 = WETH: 500000 SYNC and 1.5 WETH removed
+    let data: String = format!("{}{}", word("69e10de76676d0800000"), word("14d1120d7b160000"));
+    let pool_info: PoolInfo = pool_fixture(true);
+    let liquidity: DecodedLiquidity = decode_liquidity_log(&log_fixture(v2_burn_topic(), data), &pool_info).expect("Failed to decode burn");
+    assert_eq!(liquidity.kind, LiquidityEventKind::Burn);
+    assert!(decode_swap_log(&log_fixture(v2_burn_topic(), format!("{}{}", word("1"), word("1"))), &pool_info).is_none());
+
+    let event: LiquidityEvent = into_liquidity_event(&liquidity, &pool_info, "2023-12-06T19:54:23Z", Address::zero()).expect("Failed to shape event");
+    assert_eq!((event.amount_base, event.amount_quote), (500000.0, 1.5));
+    assert_eq!(event.block_num, 18729485);
+  }
+
+  #[test]
+  fn it_ignores_other_events() {
+    let sync_topic: H256 = H256::from(keccak256("Sync(uint112,uint112)"));
+    assert!(decode_swap_log(&log_fixture(sync_topic, format!("{}{}", word("1"), word("1"))), &pool_fixture(true)).is_none());
+    assert!(decode_liquidity_log(&log_fixture(sync_topic, format!("{}{}", word("1"), word("1"))), &pool_fixture(true)).is_none());
+  }
+}
@@ -0,0 +1,202 @@
+use crate::models::general::{FundingTransfer, Side, TradeTx, WashReason};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Wash Score
+/// Trades seen for an address and how many of them were legs of a suspected wash round trip
+/// Legs only count as wash once the address has taken part in min_round_trips matched round trips
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct WashScore {
+  pub trade_count: u64,
+  pub round_trips: u64,
+  pub wash_trade_count: u64,
+  pub wash_volume_quote: f64,
+  matched_trade_count: u64,
+  matched_volume_quote: f64,
+}
+
+impl WashScore {
+  /// Score
+  /// Share of the address's trades that were wash legs, from 0 (organic) to 1
+  pub fn score(&self) -> f64 {
+    if self.trade_count > 0 { self.wash_trade_count as f64 / self.trade_count as f64 } else { 0.0 }
+  }
+
+  /// Adds a matched leg, counting every matched leg so far as wash once enough round trips were seen
+  fn add_leg(&mut self, volume_quote: f64, min_round_trips: u64) {
+    self.matched_trade_count += 1;
+    self.matched_volume_quote += volume_quote;
+    if self.round_trips >= min_round_trips {
+      self.wash_trade_count = self.matched_trade_count;
+      self.wash_volume_quote = self.matched_volume_quote;
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+struct RecentTrade {
+  block_num: u64,
+  account_addr: String,
+  side: Side,
+  volume_base: f64,
+  volume_quote: f64,
+}
+
+/// Wash Detector
+/// Flags a trade as wash when it reverses a recent opposite-side trade of near-identical base size
+/// within window_blocks, either by the same address (self-trade) or by another address where both
+/// wallets first traded within window_blocks of each other and share a funding parent, or one funded
+/// the other (fresh wallets ping-ponging volume)
+/// At launch every wallet is fresh, so without funding transfers only self-trades are flagged
+/// A single flip is an ordinary scalp, so trades are only flagged once the trading address has taken part
+/// in min_round_trips matched round trips
+/// The trade completing the round trip carries the flag; both legs count towards the addresses' scores
+/// Trades must arrive in block order, as they do from every data source
+#[derive(Debug, Clone)]
+pub struct WashDetector {
+  pub window_blocks: u64,
+  pub size_tolerance: f64,
+  pub min_round_trips: u64,
+  pub scores: HashMap<String, WashScore>,
+  first_blocks: HashMap<String, u64>,
+  funders: HashMap<String, String>,
+  recent: VecDeque<RecentTrade>,
+}
+
+impl Default for WashDetector {
+  fn default() -> Self {
+    Self::new(0, 0.0, u64::MAX)
+  }
+}
+
+impl WashDetector {
+  pub fn new(window_blocks: u64, size_tolerance: f64, min_round_trips: u64) -> Self {
+    Self { window_blocks, size_tolerance, min_round_trips, scores: HashMap::new(), first_blocks: HashMap::new(), funders: HashMap::new(), recent: VecDeque::new() }
+  }
+
+  /// Add Funding Transfers
+  /// Records each address's funding parent, keeping the earliest transfer into it
+  pub fn add_funding_transfers(&mut self, transfers: &[FundingTransfer]) {
+    let mut ordered: Vec<&FundingTransfer> = transfers.iter().collect();
+    ordered.sort_by_key(|transfer| transfer.block_num);
+    for transfer in ordered {
+      self.funders.entry(transfer.to.to_lowercase()).or_insert_with(|| transfer.from.to_lowercase());
+    }
+  }
+
+  /// Observe
+  /// Scores a trade and returns why it was counted as wash, if it was
+  pub fn observe(&mut self, trade_tx: &TradeTx) -> Option<WashReason> {
+    let block_num: u64 = trade_tx.block_num;
+    let first_block: u64 = *self.first_blocks.entry(trade_tx.account_addr.clone()).or_insert(block_num);
+    while self.recent.front().is_some_and(|recent| recent.block_num + self.window_blocks < block_num) {
+      self.recent.pop_front();
+    }
+    self.scores.entry(trade_tx.account_addr.clone()).or_default().trade_count += 1;
+
+    let matched: Option<(usize, WashReason)> = self.recent.iter().enumerate().rev().find_map(|(idx, recent)| {
+      if recent.side == trade_tx.side || !self.is_similar_size(recent.volume_base, trade_tx.volume_base) { return None; }
+      if recent.account_addr == trade_tx.account_addr { return Some((idx, WashReason::SelfTrade)); }
+      let recent_first_block: u64 = self.first_blocks.get(&recent.account_addr).copied().unwrap_or(recent.block_num);
+      let is_fresh_pair: bool = first_block.abs_diff(recent_first_block) <= self.window_blocks;
+      if is_fresh_pair && self.is_funding_linked(&recent.account_addr, &trade_tx.account_addr) { return Some((idx, WashReason::FreshWalletPingPong)); }
+      None
+    });
+
+    match matched {
+      Some((idx, reason)) => {
+        // A leg is only matched once
+        let counterpart: RecentTrade = self.recent.remove(idx).expect("Recent trade");
+        if counterpart.account_addr != trade_tx.account_addr {
+          self.scores.entry(counterpart.account_addr.clone()).or_default().round_trips += 1;
+        }
+        let score: &mut WashScore = self.scores.entry(trade_tx.account_addr.clone()).or_default();
+        score.round_trips += 1;
+        let is_wash: bool = score.round_trips >= self.min_round_trips;
+        score.add_leg(trade_tx.volume_quote, self.min_round_trips);
+        self.scores.entry(counterpart.account_addr).or_default().add_leg(counterpart.volume_quote, self.min_round_trips);
+        if is_wash { Some(reason) } else { None }
+      },
+      None => {
+        self.recent.push_back(RecentTrade {
+          block_num,
+          account_addr: trade_tx.account_addr.clone(),
+          side: trade_tx.side.clone(),
+          volume_base: trade_tx.volume_base,
+          volume_quote: trade_tx.volume_quote
+        });
+        None
+      }
+    }
+  }
+
+  /// Score For
+  /// Returns the address's wash score, 0 if it has not traded
+  pub fn score_for(&self, address: &str) -> f64 {
+    self.scores.get(address).map(|score| score.score()).unwrap_or(0.0)
+  }
+
+  fn is_funding_linked(&self, a: &str, b: &str) -> bool {
+    let (a, b) = (a.to_lowercase(), b.to_lowercase());
+    match (self.funders.get(&a), self.funders.get(&b)) {
+      (Some(funder_a), Some(funder_b)) if funder_a == funder_b => true,
+      (Some(funder_a), _) if *funder_a == b => true,
+      (_, Some(funder_b)) => *funder_b == a,
+      _ => false
+    }
+  }
+
+  fn is_similar_size(&self, a: f64, b: f64) -> bool {
+    let largest: f64 = a.abs().max(b.abs());
+    largest > 0.0 && (a - b).abs() / largest <= self.size_tolerance
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn it_flags_self_trades_and_fresh_wallet_ping_pong() {
+    let mut detector: WashDetector = WashDetector::new(3, 0.05, 1);
+    assert_eq!(detector.observe(&TradeTx::test_trade(100, Side::Buy, "0xa", 100.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(101, Side::Sell, "0xa", 98.0, 1.0)), Some(WashReason::SelfTrade));
+    // The buy was matched already so a second sell is organic
+    assert_eq!(detector.observe(&TradeTx::test_trade(101, Side::Sell, "0xa", 98.0, 1.0)), None);
+    assert!((detector.score_for("0xa") - 2.0 / 3.0).abs() < 1e-9);
+
+    // Fresh wallets from the same funder passing the same size back and forth
+    detector.add_funding_transfers(&[
+      FundingTransfer { from: "0xf".to_string(), to: "0xb".to_string(), block_num: 90, amount_eth: 1.0 },
+      FundingTransfer { from: "0xF".to_string(), to: "0xc".to_string(), block_num: 91, amount_eth: 1.0 }
+    ]);
+    assert_eq!(detector.observe(&TradeTx::test_trade(110, Side::Buy, "0xb", 50.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(111, Side::Sell, "0xc", 50.0, 1.0)), Some(WashReason::FreshWalletPingPong));
+    assert_eq!(detector.score_for("0xb"), 1.0);
+
+    // Unrelated fresh wallets flipping a matching size, e.g. a sniper selling to a new buyer, are organic
+    assert_eq!(detector.observe(&TradeTx::test_trade(112, Side::Buy, "0xsniper", 40.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(113, Side::Sell, "0xflipper", 40.0, 1.0)), None);
+
+    // Outside the window or a different size is organic
+    assert_eq!(detector.observe(&TradeTx::test_trade(120, Side::Buy, "0xd", 10.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(124, Side::Sell, "0xd", 10.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(125, Side::Buy, "0xd", 20.0, 1.0)), None);
+    assert_eq!(detector.score_for("0xd"), 0.0);
+  }
+
+  #[test]
+  fn it_needs_repeated_round_trips_before_flagging() {
+    let mut detector: WashDetector = WashDetector::new(3, 0.05, 2);
+    // A single quick flip, e.g. a sniper taking profit, is organic
+    assert_eq!(detector.observe(&TradeTx::test_trade(100, Side::Buy, "0xa", 100.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(101, Side::Sell, "0xa", 100.0, 1.0)), None);
+    assert_eq!(detector.score_for("0xa"), 0.0);
+
+    // Going back and forth again makes every leg wash
+    assert_eq!(detector.observe(&TradeTx::test_trade(102, Side::Buy, "0xa", 100.0, 1.0)), None);
+    assert_eq!(detector.observe(&TradeTx::test_trade(103, Side::Sell, "0xa", 100.0, 1.0)), Some(WashReason::SelfTrade));
+    assert_eq!(detector.scores["0xa"].round_trips, 2);
+    assert_eq!(detector.score_for("0xa"), 1.0);
+  }
+}